@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Context, Result};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Folds players with several accounts into one, from a file mapping a canonical name to the
+/// names and platform ids (e.g. "Steam:76561198000000000") of their accounts:
+///
+/// ```toml
+/// alice = ["alice_alt", "Epic:1234"]
+/// ```
+///
+/// The same as a JSON object works too, for files ending in `.json`.
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    /// Lowercased alias to canonical name. The canonical names map to themselves.
+    canonical: HashMap<String, String>,
+}
+
+impl Aliases {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Could not read alias file {}", path.to_string_lossy()))?;
+        let map: HashMap<String, Vec<String>> = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&data).map_err(|e| anyhow!(e))
+        } else {
+            toml::from_str(&data).map_err(|e| anyhow!(e))
+        }
+        .with_context(|| format!("Alias file {} is not valid", path.to_string_lossy()))?;
+        Self::new(map)
+    }
+
+    /// Fails if an alias is given to more than one player.
+    pub fn new(map: HashMap<String, Vec<String>>) -> Result<Self> {
+        let mut canonical = HashMap::new();
+        for (name, aliases) in map {
+            for alias in aliases.iter().chain([&name]) {
+                if let Some(other) = canonical.insert(alias.to_lowercase(), name.clone()) {
+                    if other != name {
+                        return Err(anyhow!(
+                            "{} is an alias of both {} and {}",
+                            alias,
+                            other,
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Self { canonical })
+    }
+
+    /// The canonical name of a player, looked up by platform id first and then by name.
+    /// Nothing for players without an alias.
+    pub fn resolve(&self, id: &str, name: &str) -> Option<&str> {
+        self.canonical
+            .get(&id.to_lowercase())
+            .or_else(|| self.canonical.get(&name.to_lowercase()))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> Aliases {
+        Aliases::new(HashMap::from([(
+            "Alice".to_string(),
+            vec!["alice_alt".to_string(), "Epic:1234".to_string()],
+        )]))
+        .unwrap()
+    }
+
+    #[test]
+    fn resolve_by_id_or_name() {
+        let aliases = aliases();
+        assert_eq!(aliases.resolve("Epic:1234", "someone"), Some("Alice"));
+        assert_eq!(aliases.resolve("Steam:1", "ALICE_ALT"), Some("Alice"));
+        assert_eq!(aliases.resolve("Steam:2", "alice"), Some("Alice"));
+        assert_eq!(aliases.resolve("bob", "bob"), None);
+    }
+
+    #[test]
+    fn alias_of_two_players() {
+        let map = HashMap::from([
+            ("alice".to_string(), vec!["shared".to_string()]),
+            ("bob".to_string(), vec!["Shared".to_string()]),
+        ]);
+        assert!(Aliases::new(map).is_err());
+    }
+}
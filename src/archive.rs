@@ -0,0 +1,28 @@
+use anyhow::{Context, Result};
+use std::{fs::File, io::Read, path::Path};
+use zip::ZipArchive;
+
+/// Hands every `.replay` (or `.replay.gz`) in the zip file at `path` to `read`, with its name in the archive,
+/// one at a time and without extracting anything to disk. Everything else in it is skipped.
+pub fn read_replays(path: &Path, mut read: impl FnMut(&str, &[u8])) -> Result<usize> {
+    let file = File::open(path)
+        .with_context(|| format!("Could not open zip file {}", path.to_string_lossy()))?;
+    let mut archive = ZipArchive::new(file)
+        .with_context(|| format!("{} is not a valid zip file", path.to_string_lossy()))?;
+    let mut replays = 0;
+    let mut data = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name()?.into_owned();
+        if !entry.is_file() || !crate::is_replay(Path::new(&name)) {
+            continue;
+        }
+        data.clear();
+        entry
+            .read_to_end(&mut data)
+            .with_context(|| format!("Could not unpack {}", name))?;
+        read(&name, &data);
+        replays += 1;
+    }
+    Ok(replays)
+}
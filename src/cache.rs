@@ -0,0 +1,92 @@
+use crate::Game;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tracing::warn;
+
+/// What was read from replays before, so `--backfill` does not parse them again on every
+/// start. Replays are looked up by path and only used while their size and modification time
+/// are the same as when they were read.
+pub struct ReplayCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    modified: SystemTime,
+    size: u64,
+    /// Whether the replay was read with `--deep-parse`, which finds more in it.
+    deep_parse: bool,
+    /// Nothing for replays that are not a match.
+    game: Option<Game>,
+}
+
+impl ReplayCache {
+    /// Loads the cache at `path`. A cache that is missing or cannot be read starts out empty,
+    /// as everything in it can be read from the replays again.
+    pub fn load(path: &Path) -> Self {
+        let entries = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                warn!(
+                    "Replay cache {} is not valid, starting over: {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path: path.to_path_buf(),
+            entries,
+        }
+    }
+
+    /// What was read from the replay before, if it has not changed since.
+    pub fn get(&self, replay: &Path, deep_parse: bool) -> Option<Option<Game>> {
+        let entry = self.entries.get(replay)?;
+        let (modified, size) = file_version(replay)?;
+        // A cache without the network data does not do for --deep-parse, the other way round is fine.
+        let usable =
+            entry.modified == modified && entry.size == size && (entry.deep_parse || !deep_parse);
+        usable.then(|| entry.game.clone())
+    }
+
+    pub fn insert(&mut self, replay: &Path, deep_parse: bool, game: Option<&Game>) {
+        let Some((modified, size)) = file_version(replay) else {
+            return;
+        };
+        self.entries.insert(
+            replay.to_path_buf(),
+            Entry {
+                modified,
+                size,
+                deep_parse,
+                game: game.cloned(),
+            },
+        );
+    }
+
+    /// Writes the cache, leaving out replays that are gone.
+    pub fn save(&mut self) -> Result<()> {
+        self.entries.retain(|replay, _| replay.exists());
+        let json = serde_json::to_string(&self.entries)?;
+        crate::write_atomic(&self.path, &json).with_context(|| {
+            format!(
+                "Could not write replay cache {}",
+                self.path.to_string_lossy()
+            )
+        })
+    }
+}
+
+fn file_version(replay: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(replay).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -0,0 +1,82 @@
+use anyhow::{anyhow, Result};
+use image::{ImageOutputFormat, RgbImage};
+use plotters::prelude::*;
+use std::io::Cursor;
+
+/// Size of the chart in pixels, about what Discord shows an image at.
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 450;
+/// The blurple Discord uses for its own charts.
+const BAR: RGBColor = RGBColor(0x58, 0x65, 0xF2);
+
+/// Draws a bar per player, e.g. of their goals, and returns it as a PNG. Nothing without
+/// players, as an empty chart tells nobody anything.
+pub fn bar_chart(title: &str, bars: &[(String, usize)]) -> Result<Option<Vec<u8>>> {
+    if bars.is_empty() {
+        return Ok(None);
+    }
+    let mut pixels = vec![0; (WIDTH * HEIGHT * 3) as usize];
+    draw(&mut pixels, title, bars).map_err(|e| anyhow!("Could not draw chart: {}", e))?;
+    let image = RgbImage::from_raw(WIDTH, HEIGHT, pixels).expect("buffer fits the image");
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(Some(png.into_inner()))
+}
+
+fn draw(
+    pixels: &mut [u8],
+    title: &str,
+    bars: &[(String, usize)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let root = BitMapBackend::with_buffer(pixels, (WIDTH, HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    // Some headroom above the highest bar, and an axis that goes up to 1 when nobody scored.
+    let top = bars
+        .iter()
+        .map(|(_, value)| *value)
+        .max()
+        .unwrap_or_default()
+        + 1;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d((0..bars.len() - 1).into_segmented(), 0..top)?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(bars.len())
+        .x_label_formatter(&|x| match x {
+            SegmentValue::CenterOf(i) | SegmentValue::Exact(i) => bars
+                .get(*i)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default(),
+            SegmentValue::Last => String::new(),
+        })
+        .label_style(("sans-serif", 16))
+        .draw()?;
+    chart.draw_series(
+        Histogram::vertical(&chart)
+            .style(BAR.filled())
+            .margin(15)
+            .data(bars.iter().enumerate().map(|(i, (_, value))| (i, *value))),
+    )?;
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chart_of_players() {
+        assert!(bar_chart("Goals", &[]).unwrap().is_none());
+        let bars = [("alice".to_string(), 4), ("bob".to_string(), 0)];
+        let png = bar_chart("Goals", &bars).unwrap().unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        // A single player, or nobody having scored, must not trip up the axes.
+        assert!(bar_chart("Goals", &bars[1..]).unwrap().is_some());
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::{fs, path::Path, path::PathBuf};
+
+use crate::Column;
+
+/// Config file that is picked up from the working directory when `--config` is not given.
+pub const DEFAULT_CONFIG: &str = "rl-session.toml";
+
+/// Settings that can be kept in the config file instead of being passed on every launch.
+/// Flags given on the command line always win over the values in here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub location: Option<Locations>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub webhook: Vec<String>,
+    pub no_discord: Option<bool>,
+    pub no_start_message: Option<bool>,
+    pub eager_start_message: Option<bool>,
+    pub template: Option<String>,
+    pub columns: Option<Vec<Column>>,
+    pub bot_name: Option<String>,
+    pub session_title: Option<String>,
+    pub secrets: Option<PathBuf>,
+}
+
+/// Either a single replay folder or a list of them.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Locations {
+    One(PathBuf),
+    Many(Vec<PathBuf>),
+}
+
+impl From<Locations> for Vec<PathBuf> {
+    fn from(locations: Locations) -> Self {
+        match locations {
+            Locations::One(location) => vec![location],
+            Locations::Many(locations) => locations,
+        }
+    }
+}
+
+/// Either a single webhook or a list of them, all of which get every message.
+pub(crate) fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(webhook) => vec![webhook],
+        OneOrMany::Many(webhooks) => webhooks,
+    })
+}
+
+impl FileConfig {
+    /// Loads the config at `path`, or the default config if no path is given.
+    /// A missing default config is not an error, but a missing explicit one is.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path, true),
+            None => (Path::new(DEFAULT_CONFIG), false),
+        };
+        if !explicit && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.to_string_lossy()))?;
+        Self::parse(&data)
+            .with_context(|| format!("Config file {} is not valid", path.to_string_lossy()))
+    }
+
+    fn parse(data: &str) -> Result<Self> {
+        let deserializer = toml::Deserializer::new(data);
+        serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            match e.path().to_string().as_str() {
+                "." => anyhow!(e.inner().to_string()),
+                key => anyhow!("Error at key `{}`: {}", key, e.inner().message()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_or_many_webhooks() {
+        let config = FileConfig::parse("webhook = \"https://a\"").unwrap();
+        assert_eq!(config.webhook, ["https://a"]);
+        let config = FileConfig::parse("webhook = [\"https://a\", \"https://b\"]").unwrap();
+        assert_eq!(config.webhook, ["https://a", "https://b"]);
+        assert!(FileConfig::parse("").unwrap().webhook.is_empty());
+    }
+}
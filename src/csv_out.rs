@@ -0,0 +1,79 @@
+use crate::Game;
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+};
+
+/// A CSV file that gets a row for every player in every game, for spreadsheets. Rows are
+/// appended, so the file can be kept across sessions.
+pub struct CsvLog {
+    writer: csv::Writer<File>,
+}
+
+/// A row of the file. Renaming fields changes the header.
+#[derive(Serialize)]
+struct Row<'a> {
+    /// When the game was played in RFC 3339, or when it was recorded if we do not know.
+    timestamp: String,
+    replay_file: String,
+    playlist: &'a str,
+    team0_score: i32,
+    team1_score: i32,
+    player_id: &'a str,
+    name: &'a str,
+    /// Empty for games without a result for the player.
+    result: &'a str,
+    mvp: bool,
+    score: usize,
+    goals: usize,
+    assists: usize,
+    saves: usize,
+    shots: usize,
+}
+
+impl CsvLog {
+    /// Opens the file for appending, writing the header first if it is new or empty.
+    pub fn open(path: &Path) -> Result<CsvLog> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Could not open csv output {}", path.to_string_lossy()))?;
+        let is_new = file.metadata()?.len() == 0;
+        let writer = csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file);
+        Ok(CsvLog { writer })
+    }
+
+    /// Appends a row for every player in the game and flushes, so the file is always current.
+    pub fn record(&mut self, replay: &Path, game: &Game) -> Result<()> {
+        let timestamp = game.played_at.unwrap_or_else(Local::now).to_rfc3339();
+        for player in &game.players {
+            self.writer.serialize(Row {
+                timestamp: timestamp.clone(),
+                replay_file: replay.to_string_lossy().into_owned(),
+                playlist: &game.playlist,
+                team0_score: game.team_scores.0,
+                team1_score: game.team_scores.1,
+                player_id: &player.id,
+                name: &player.name,
+                result: player
+                    .outcome
+                    .map(|outcome| outcome.as_str())
+                    .unwrap_or_default(),
+                mvp: player.mvp,
+                score: player.score,
+                goals: player.goals,
+                assists: player.assists,
+                saves: player.saves,
+                shots: player.shots,
+            })?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Game, Outcome};
+use anyhow::{Context, Result};
+use chrono::Local;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+/// An append-only log of every game, kept across sessions. The live session never reads from it.
+pub struct Db {
+    conn: Connection,
+}
+
+/// What a player has done over every game in the database.
+pub struct Lifetime {
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub goals: usize,
+}
+
+impl Db {
+    pub fn open(path: &Path) -> Result<Db> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Could not open database {}", path.to_string_lossy()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS games (
+                id INTEGER PRIMARY KEY,
+                recorded_at INTEGER NOT NULL,
+                replay TEXT NOT NULL,
+                playlist TEXT NOT NULL,
+                forfeit INTEGER NOT NULL,
+                team0_score INTEGER NOT NULL,
+                team1_score INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS player_games (
+                game_id INTEGER NOT NULL REFERENCES games(id),
+                player_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                outcome TEXT,
+                mvp INTEGER NOT NULL,
+                score INTEGER NOT NULL,
+                goals INTEGER NOT NULL,
+                assists INTEGER NOT NULL,
+                saves INTEGER NOT NULL,
+                shots INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Db { conn })
+    }
+
+    /// Adds a game and the stats of everyone in it. A replay that is already in the database,
+    /// e.g. because it was backfilled again, is not added twice. It is dated when it was played,
+    /// or now if the replay does not say.
+    pub fn record(&mut self, replay: &Path, game: &Game) -> Result<()> {
+        let known = self
+            .conn
+            .prepare("SELECT 1 FROM games WHERE replay = ?1")?
+            .exists([replay.to_string_lossy()])?;
+        if known {
+            return Ok(());
+        }
+        let recorded_at = game.played_at.unwrap_or_else(Local::now).timestamp();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO games (recorded_at, replay, playlist, forfeit, team0_score, team1_score)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                recorded_at,
+                replay.to_string_lossy(),
+                game.playlist,
+                game.forfeit,
+                game.team_scores.0,
+                game.team_scores.1
+            ],
+        )?;
+        let game_id = tx.last_insert_rowid();
+        for player in &game.players {
+            let outcome = player.outcome.map(Outcome::as_str);
+            tx.execute(
+                "INSERT INTO player_games
+                (game_id, player_id, name, outcome, mvp, score, goals, assists, saves, shots)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    game_id,
+                    player.id,
+                    player.name,
+                    outcome,
+                    player.mvp,
+                    player.score as i64,
+                    player.goals as i64,
+                    player.assists as i64,
+                    player.saves as i64,
+                    player.shots as i64
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every player's lifetime stats, most games first, under the name they last played with.
+    pub fn lifetime(&self) -> Result<Vec<Lifetime>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT
+                (SELECT name FROM player_games latest WHERE latest.player_id = p.player_id
+                    ORDER BY latest.game_id DESC LIMIT 1),
+                COUNT(*),
+                SUM(outcome = 'win'),
+                SUM(outcome = 'loss'),
+                SUM(goals)
+            FROM player_games p
+            GROUP BY player_id
+            ORDER BY COUNT(*) DESC",
+        )?;
+        // Sums are NULL when there is nothing to add up, e.g. no game with a result.
+        let count = |row: &rusqlite::Row, i| {
+            row.get::<_, Option<i64>>(i)
+                .map(|n| n.unwrap_or_default() as usize)
+        };
+        let rows = stmt.query_map([], |row| {
+            Ok(Lifetime {
+                name: row.get(0)?,
+                games: count(row, 1)?,
+                wins: count(row, 2)?,
+                losses: count(row, 3)?,
+                goals: count(row, 4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
@@ -0,0 +1,274 @@
+use boxcars::{ActorId, Attribute, Replay, Vector3f};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Stats that are only in the network data of a replay, see `--deep-parse`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeepStats {
+    pub demos_inflicted: usize,
+    pub demos_taken: usize,
+    /// Boost in the 0-100 units of the boost meter.
+    pub boost_collected: usize,
+    pub boost_used: usize,
+    /// Touches that took the ball from the other team. The replay only records which team
+    /// touched the ball last, so a dribble or pass within a team does not count.
+    pub touches: usize,
+    /// Goals put into their own net, going by who touched the ball last. They are not in the
+    /// goals of anyone, as the replay header does not count them.
+    #[serde(default)]
+    pub own_goals: usize,
+}
+
+impl DeepStats {
+    pub fn add(&mut self, other: &DeepStats) {
+        self.demos_inflicted += other.demos_inflicted;
+        self.demos_taken += other.demos_taken;
+        self.boost_collected += other.boost_collected;
+        self.boost_used += other.boost_used;
+        self.touches += other.touches;
+        self.own_goals += other.own_goals;
+    }
+}
+
+/// The same demolition is replicated for a few frames, anything within this many seconds is one demo.
+const DEMO_REPEAT_TIME: f32 = 1.0;
+
+/// Walks the network frames and sums up the deep stats of every player, keyed by player name.
+/// Returns nothing if the replay was parsed without network data.
+pub fn deep_stats(replay: &Replay) -> Option<HashMap<String, DeepStats>> {
+    let frames = &replay.network_frames.as_ref()?.frames;
+    let object = |id: boxcars::ObjectId| {
+        replay
+            .objects
+            .get(usize::from(id))
+            .map(String::as_str)
+            .unwrap_or_default()
+    };
+
+    // Actor ids are reused once an actor is deleted, so everything is forgotten on deletion.
+    let mut names: HashMap<ActorId, String> = HashMap::new();
+    let mut pri_team: HashMap<ActorId, ActorId> = HashMap::new();
+    let mut teams: HashMap<ActorId, u8> = HashMap::new();
+    let mut car_pri: HashMap<ActorId, ActorId> = HashMap::new();
+    let mut component_car: HashMap<ActorId, ActorId> = HashMap::new();
+    let mut boost: HashMap<ActorId, u8> = HashMap::new();
+    let mut locations: HashMap<ActorId, Vector3f> = HashMap::new();
+    let mut last_demo: HashMap<ActorId, (ActorId, ActorId, f32)> = HashMap::new();
+    // The team that touched the ball last, and who of them, until the next goal.
+    let mut last_touch: Option<(u8, Option<String>)> = None;
+
+    let mut stats: HashMap<String, DeepStats> = HashMap::new();
+    for frame in frames {
+        for actor in &frame.deleted_actors {
+            names.remove(actor);
+            pri_team.remove(actor);
+            teams.remove(actor);
+            car_pri.remove(actor);
+            component_car.remove(actor);
+            boost.remove(actor);
+            locations.remove(actor);
+            last_demo.remove(actor);
+        }
+        for actor in &frame.new_actors {
+            let team = object(actor.object_id).strip_prefix("Archetypes.Teams.Team");
+            if let Some(Ok(team)) = team.map(str::parse) {
+                teams.insert(actor.actor_id, team);
+            }
+        }
+
+        for update in &frame.updated_actors {
+            let actor = update.actor_id;
+            let player = |car| driver(car, &car_pri, &component_car, &names);
+            match (object(update.object_id), &update.attribute) {
+                ("Engine.PlayerReplicationInfo:PlayerName", Attribute::String(name)) => {
+                    names.insert(actor, name.clone());
+                }
+                ("Engine.PlayerReplicationInfo:Team", Attribute::ActiveActor(team)) => {
+                    pri_team.insert(actor, team.actor);
+                }
+                ("Engine.Pawn:PlayerReplicationInfo", Attribute::ActiveActor(pri)) => {
+                    car_pri.insert(actor, pri.actor);
+                }
+                ("TAGame.CarComponent_TA:Vehicle", Attribute::ActiveActor(car)) => {
+                    component_car.insert(actor, car.actor);
+                }
+                ("TAGame.RBActor_TA:ReplicatedRBState", Attribute::RigidBody(body)) => {
+                    locations.insert(actor, body.location);
+                }
+                ("TAGame.CarComponent_Boost_TA:ReplicatedBoostAmount", Attribute::Byte(amount)) => {
+                    // The first amount is what the car spawned with, only changes after it count.
+                    let Some(previous) = boost.insert(actor, *amount) else {
+                        continue;
+                    };
+                    let Some(name) = player(actor) else {
+                        continue;
+                    };
+                    let entry = stats.entry(name).or_default();
+                    if *amount > previous {
+                        entry.boost_collected += (*amount - previous) as usize;
+                    } else {
+                        entry.boost_used += (previous - *amount) as usize;
+                    }
+                }
+                (
+                    "TAGame.Car_TA:ReplicatedDemolish"
+                    | "TAGame.Car_TA:ReplicatedDemolish_CustomFX"
+                    | "TAGame.Car_TA:ReplicatedDemolishGoalExplosion",
+                    attribute,
+                ) => {
+                    let (attacker, victim) = match attribute {
+                        Attribute::Demolish(demo) => (demo.attacker, demo.victim),
+                        Attribute::DemolishFx(demo) => (demo.attacker, demo.victim),
+                        _ => continue,
+                    };
+                    let repeated = last_demo.get(&actor).is_some_and(|&(a, v, time)| {
+                        a == attacker && v == victim && frame.time - time < DEMO_REPEAT_TIME
+                    });
+                    last_demo.insert(actor, (attacker, victim, frame.time));
+                    if repeated {
+                        continue;
+                    }
+                    if let Some(name) = player(attacker) {
+                        stats.entry(name).or_default().demos_inflicted += 1;
+                    }
+                    if let Some(name) = player(victim) {
+                        stats.entry(name).or_default().demos_taken += 1;
+                    }
+                }
+                ("TAGame.Ball_TA:HitTeamNum", Attribute::Byte(team)) => {
+                    // The toucher is taken to be the car of that team closest to the ball.
+                    let Some(ball) = locations.get(&actor) else {
+                        continue;
+                    };
+                    let toucher = car_pri
+                        .iter()
+                        .filter(|(_, pri)| {
+                            pri_team
+                                .get(pri)
+                                .and_then(|team| teams.get(team))
+                                .is_some_and(|t| t == team)
+                        })
+                        .filter_map(|(car, pri)| Some((locations.get(car)?, pri)))
+                        .min_by(|(a, _), (b, _)| distance(a, ball).total_cmp(&distance(b, ball)))
+                        .and_then(|(_, pri)| names.get(pri))
+                        .cloned();
+                    if let Some(name) = &toucher {
+                        stats.entry(name.clone()).or_default().touches += 1;
+                    }
+                    last_touch = Some((*team, toucher));
+                }
+                (
+                    "TAGame.Ball_TA:ReplicatedExplosionData"
+                    | "TAGame.Ball_TA:ReplicatedExplosionDataExtended",
+                    attribute,
+                ) => {
+                    let explosion = match attribute {
+                        Attribute::Explosion(explosion) => explosion,
+                        Attribute::ExtendedExplosion(extended) => &extended.explosion,
+                        _ => continue,
+                    };
+                    // Taken, so the same explosion replicated again does not count twice.
+                    if let Some((team, Some(name))) = last_touch.take() {
+                        if team == defending_team(&explosion.location) {
+                            stats.entry(name).or_default().own_goals += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    for player in stats.values_mut() {
+        player.boost_collected = boost_meter(player.boost_collected);
+        player.boost_used = boost_meter(player.boost_used);
+    }
+    Some(stats)
+}
+
+/// The rank tier every player was shown with, keyed by player name, from 1 for Bronze I to 22
+/// for Supersonic Legend. Players without one, e.g. in casual games, are left out.
+pub fn skill_tiers(replay: &Replay) -> HashMap<String, u8> {
+    let Some(network) = &replay.network_frames else {
+        return HashMap::new();
+    };
+    let mut names: HashMap<ActorId, String> = HashMap::new();
+    let mut tiers: HashMap<ActorId, u8> = HashMap::new();
+    let mut shown: HashMap<String, u8> = HashMap::new();
+    for frame in &network.frames {
+        for actor in &frame.deleted_actors {
+            names.remove(actor);
+            tiers.remove(actor);
+        }
+        for update in &frame.updated_actors {
+            let actor = update.actor_id;
+            let object = replay.objects.get(usize::from(update.object_id));
+            match (object.map(String::as_str), &update.attribute) {
+                (Some("Engine.PlayerReplicationInfo:PlayerName"), Attribute::String(name)) => {
+                    names.insert(actor, name.clone());
+                }
+                (Some("TAGame.PRI_TA:SkillTier"), Attribute::FlaggedByte(_, tier)) => {
+                    tiers.insert(actor, *tier);
+                }
+                _ => continue,
+            }
+            // The name and tier can come in either order.
+            if let (Some(name), Some(&tier)) = (names.get(&actor), tiers.get(&actor)) {
+                if tier > 0 {
+                    shown.insert(name.clone(), tier);
+                }
+            }
+        }
+    }
+    shown
+}
+
+/// Playlists that count towards a rank: duel, doubles, solo standard, standard, and the ranked
+/// hoops, rumble, dropshot and snow day.
+const RANKED_PLAYLISTS: &[i32] = &[10, 11, 12, 13, 27, 28, 29, 30];
+
+/// Whether the game was in a ranked playlist. Only the network data says which playlist it was,
+/// so this is nothing for a replay parsed without it.
+pub fn ranked(replay: &Replay) -> Option<bool> {
+    let frames = &replay.network_frames.as_ref()?.frames;
+    frames
+        .iter()
+        .flat_map(|frame| &frame.updated_actors)
+        .find_map(|update| {
+            let object = replay.objects.get(usize::from(update.object_id))?;
+            match (object.as_str(), &update.attribute) {
+                ("ProjectX.GRI_X:ReplicatedGamePlaylist", Attribute::Int(playlist)) => {
+                    Some(RANKED_PLAYLISTS.contains(playlist))
+                }
+                _ => None,
+            }
+        })
+}
+
+/// The team whose net the ball went into. Blue (0) defends the goal on the negative y side.
+pub fn defending_team(ball: &Vector3f) -> u8 {
+    u8::from(ball.y > 0.0)
+}
+
+/// Resolves a car, or a component of a car, to the name of its driver.
+fn driver(
+    actor: ActorId,
+    car_pri: &HashMap<ActorId, ActorId>,
+    component_car: &HashMap<ActorId, ActorId>,
+    names: &HashMap<ActorId, String>,
+) -> Option<String> {
+    car_pri
+        .get(&actor)
+        .or_else(|| component_car.get(&actor).and_then(|car| car_pri.get(car)))
+        .and_then(|pri| names.get(pri))
+        .cloned()
+}
+
+/// The replicated boost amount goes from 0 to 255, the meter in game from 0 to 100.
+fn boost_meter(amount: usize) -> usize {
+    (amount * 100 + 127) / 255
+}
+
+fn distance(a: &Vector3f, b: &Vector3f) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
@@ -0,0 +1,382 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use url::Url;
+use webhook::models::Message;
+
+use crate::{
+    http::JsonClient,
+    sink::{split_message, with_retry, Report, StatsSink},
+};
+
+/// Embed colors for a winning and a losing session.
+const GREEN: u32 = 0x57F287;
+const RED: u32 = 0xED4245;
+
+/// Discord refuses embeds with more fields than this.
+const MAX_FIELDS: usize = 25;
+/// Discord's length limits in characters, for an embed title and description, a field's name
+/// and value, a footer, and everything in an embed together.
+const MAX_TITLE: usize = 256;
+const MAX_DESCRIPTION: usize = 4096;
+const MAX_FIELD_NAME: usize = 256;
+const MAX_FIELD_VALUE: usize = 1024;
+const MAX_FOOTER: usize = 2048;
+const MAX_EMBED: usize = 6000;
+
+/// A Discord webhook. Messages are sent as embeds.
+pub struct Discord {
+    client: JsonClient,
+    url: String,
+    username: String,
+    /// Picture the messages are posted with instead of the webhook's own.
+    avatar_url: Option<String>,
+    /// Small picture in the corner of the first embed of every message.
+    thumbnail_url: Option<String>,
+    attempts: usize,
+}
+
+impl Discord {
+    /// Messages go into the thread `thread_id` when there is one. Fails if `url` is not a url.
+    pub fn new(
+        url: &str,
+        username: &str,
+        thread_id: Option<u64>,
+        attempts: usize,
+        client: JsonClient,
+    ) -> Result<Self> {
+        Ok(Self {
+            client,
+            url: thread_url(url, thread_id)?,
+            username: username.to_string(),
+            avatar_url: None,
+            thumbnail_url: None,
+            attempts,
+        })
+    }
+
+    /// Posts with the avatar and embed thumbnail at these urls, where given.
+    pub fn with_branding(mut self, avatar_url: Option<&str>, thumbnail_url: Option<&str>) -> Self {
+        self.avatar_url = avatar_url.map(str::to_string);
+        self.thumbnail_url = thumbnail_url.map(str::to_string);
+        self
+    }
+
+    /// Sends every embed as its own message, in order.
+    async fn send_embeds(&self, embeds: &[Embed], color: Option<u32>) -> Result<()> {
+        for (i, embed) in embeds.iter().enumerate() {
+            // Built with the webhook crate's models, but sent with our client so it goes
+            // through --proxy and trusts --ca-cert.
+            let mut message = Message::new();
+            message.username(&self.username);
+            if let Some(avatar_url) = &self.avatar_url {
+                message.avatar_url(avatar_url);
+            }
+            message.embed(|e| {
+                if let Some(title) = &embed.title {
+                    e.title(title);
+                }
+                if let (0, Some(thumbnail_url)) = (i, &self.thumbnail_url) {
+                    e.thumbnail(thumbnail_url);
+                }
+                if let Some(color) = color {
+                    e.color(&color.to_string());
+                }
+                for (name, value) in &embed.fields {
+                    e.field(name, value, true);
+                }
+                if let Some(footer) = &embed.footer {
+                    e.footer(footer, None);
+                }
+                e.description(&embed.description)
+            });
+            let message = serde_json::to_value(&message)?;
+            with_retry(self.attempts, || async {
+                self.client.post(&self.url, &message).await.map(|_| ())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StatsSink for Discord {
+    async fn send_message(&self, title: Option<&str>, text: &str) -> Result<()> {
+        let embeds = Embed::from_text(title, text);
+        self.send_embeds(&embeds, None).await
+    }
+
+    /// Sends the report as an embed with a field per player, green when the win rate is at
+    /// least 50% and red otherwise. Reports too big for one embed go out as several.
+    async fn send_report(&self, report: &Report) -> Result<()> {
+        let color = if report.win_rate >= 50 { GREEN } else { RED };
+        self.send_embeds(&Embed::from_report(report), Some(color))
+            .await
+    }
+
+    /// Sends the image as an attachment shown in an embed with the title.
+    async fn send_image(&self, title: &str, png: &[u8]) -> Result<()> {
+        let mut payload = json!({
+            "username": self.username,
+            "embeds": [{ "title": title, "image": { "url": "attachment://chart.png" } }],
+            "attachments": [{ "id": 0, "filename": "chart.png" }],
+        });
+        if let Some(avatar_url) = &self.avatar_url {
+            payload["avatar_url"] = json!(avatar_url);
+        }
+        with_retry(self.attempts, || async {
+            self.client
+                .post_file(&self.url, &payload, "chart.png", "image/png", png)
+                .await
+                .map(|_| ())
+        })
+        .await
+    }
+}
+
+/// The parts of an embed we fill in, kept within Discord's limits.
+#[derive(Debug, Default)]
+struct Embed {
+    title: Option<String>,
+    description: String,
+    fields: Vec<(String, String)>,
+    footer: Option<String>,
+}
+
+impl Embed {
+    /// The text as descriptions, split over several embeds if it is too long. Only the
+    /// first one has the title.
+    fn from_text(title: Option<&str>, text: &str) -> Vec<Embed> {
+        let mut embeds: Vec<Embed> = split_message(text, MAX_DESCRIPTION)
+            .into_iter()
+            .map(|description| Embed {
+                description,
+                ..Default::default()
+            })
+            .collect();
+        if embeds.is_empty() {
+            embeds.push(Embed::default());
+        }
+        embeds[0].title = title.map(|title| truncate(title, MAX_TITLE));
+        embeds
+    }
+
+    /// The header as description and a field per player, starting a new embed whenever one
+    /// runs out of fields or characters. The footer goes below the last one, or in an embed of
+    /// its own if it does not fit there.
+    fn from_report(report: &Report) -> Vec<Embed> {
+        let mut embeds = Embed::from_text(report.title.as_deref(), &report.header);
+        // A group gets a field of its own with the subtotal, in front of its players.
+        let fields = report
+            .grouped_players()
+            .into_iter()
+            .flat_map(|(group, player)| {
+                group
+                    .map(|group| (&group.name, &group.subtotal))
+                    .into_iter()
+                    .chain([(&player.heading, &player.stats)])
+            });
+        for (name, value) in fields {
+            let field = (
+                truncate(name, MAX_FIELD_NAME),
+                truncate(value, MAX_FIELD_VALUE),
+            );
+            let field_len = field.0.chars().count() + field.1.chars().count();
+            let last = embeds.last().expect("there is always at least one embed");
+            if last.fields.len() >= MAX_FIELDS || last.len() + field_len > MAX_EMBED {
+                embeds.push(Embed::default());
+            }
+            if let Some(last) = embeds.last_mut() {
+                last.fields.push(field);
+            }
+        }
+        if let Some(footer) = &report.footer {
+            let footer = truncate(footer, MAX_FOOTER);
+            let last = embeds.last().expect("there is always at least one embed");
+            if last.len() + footer.chars().count() > MAX_EMBED {
+                embeds.push(Embed::default());
+            }
+            if let Some(last) = embeds.last_mut() {
+                last.footer = Some(footer);
+            }
+        }
+        embeds
+    }
+
+    /// Characters counting towards the limit of an embed.
+    fn len(&self) -> usize {
+        self.title.as_deref().unwrap_or_default().chars().count()
+            + self.description.chars().count()
+            + self.footer.as_deref().unwrap_or_default().chars().count()
+            + self
+                .fields
+                .iter()
+                .map(|(name, value)| name.chars().count() + value.chars().count())
+                .sum::<usize>()
+    }
+}
+
+/// The webhook url that posts into the thread, which Discord takes as a query parameter. The
+/// rest of the query, e.g. `?wait=true`, is kept.
+fn thread_url(url: &str, thread_id: Option<u64>) -> Result<String> {
+    // The url has the webhook's token in it, so it is left out of the error.
+    let mut url = Url::parse(url).map_err(|e| anyhow!("The webhook is not a valid url: {}", e))?;
+    if let Some(id) = thread_id {
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| key != "thread_id")
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair("thread_id", &id.to_string());
+    }
+    Ok(url.into())
+}
+
+/// Cuts `text` down to `limit` characters, marking that something was cut off.
+fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(limit - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::PlayerReport;
+
+    fn assert_sendable(embeds: &[Embed]) {
+        for embed in embeds {
+            assert!(embed.fields.len() <= MAX_FIELDS);
+            assert!(embed.title.as_deref().unwrap_or_default().chars().count() <= MAX_TITLE);
+            assert!(embed.description.chars().count() <= MAX_DESCRIPTION);
+            assert!(embed.len() <= MAX_EMBED);
+            for (name, value) in &embed.fields {
+                assert!(name.chars().count() <= MAX_FIELD_NAME);
+                assert!(value.chars().count() <= MAX_FIELD_VALUE);
+            }
+        }
+    }
+
+    #[test]
+    fn report_with_many_players() {
+        let stats = "- Score: 1234 (567), 617 per game\n".repeat(12);
+        let report = Report {
+            title: Some("Session complete".to_string()),
+            header: "## 40 games played\n*Win rate: 55%*\n\n".to_string(),
+            players: (0..20)
+                .map(|i| PlayerReport {
+                    heading: format!("player {}", i),
+                    stats: stats.clone(),
+                    win_rate: None,
+                    group: None,
+                })
+                .collect(),
+            footer: Some("...and 2 others".to_string()),
+            win_rate: 55,
+        };
+        let embeds = Embed::from_report(&report);
+        assert_sendable(&embeds);
+        assert!(embeds.len() > 1);
+        assert_eq!(embeds[0].title.as_deref(), Some("Session complete"));
+        assert!(embeds[1..].iter().all(|embed| embed.title.is_none()));
+        let players: Vec<_> = embeds.iter().flat_map(|embed| &embed.fields).collect();
+        assert_eq!(players.len(), 20);
+        assert_eq!(players[19].0, "player 19");
+        assert_eq!(
+            embeds.last().unwrap().footer.as_deref(),
+            Some("...and 2 others")
+        );
+        assert!(embeds[0].footer.is_none());
+    }
+
+    #[test]
+    fn webhook_url_with_thread() {
+        let url = "https://discord.com/api/webhooks/1/token";
+        assert_eq!(thread_url(url, None).unwrap(), url);
+        assert_eq!(
+            thread_url(url, Some(42)).unwrap(),
+            "https://discord.com/api/webhooks/1/token?thread_id=42"
+        );
+        assert_eq!(
+            thread_url(&format!("{url}?wait=true"), Some(42)).unwrap(),
+            "https://discord.com/api/webhooks/1/token?wait=true&thread_id=42"
+        );
+        assert_eq!(
+            thread_url(&format!("{url}?wait=true"), None).unwrap(),
+            "https://discord.com/api/webhooks/1/token?wait=true"
+        );
+    }
+
+    #[test]
+    fn webhook_url_with_odd_query() {
+        let url = "https://discord.com/api/webhooks/1/token";
+        assert_eq!(
+            thread_url(&format!("{url}?"), Some(42)).unwrap(),
+            "https://discord.com/api/webhooks/1/token?thread_id=42"
+        );
+        assert_eq!(
+            thread_url(&format!("{url}?thread_id=7&wait=true"), Some(42)).unwrap(),
+            "https://discord.com/api/webhooks/1/token?wait=true&thread_id=42"
+        );
+        assert_eq!(
+            thread_url(&format!("{url}?wait=true#top"), Some(42)).unwrap(),
+            "https://discord.com/api/webhooks/1/token?wait=true&thread_id=42#top"
+        );
+        let error = thread_url("discord.com/api/webhooks/1/token", None).unwrap_err();
+        assert!(!error.to_string().contains("token"));
+    }
+
+    #[test]
+    fn oversized_text_and_fields() {
+        let report = Report {
+            title: None,
+            header: "header line\n".repeat(500),
+            players: vec![PlayerReport {
+                heading: "x".repeat(300),
+                stats: "y".repeat(2000),
+                win_rate: None,
+                group: None,
+            }],
+            footer: None,
+            win_rate: 0,
+        };
+        let embeds = Embed::from_report(&report);
+        assert_sendable(&embeds);
+        assert_eq!(embeds.len(), 2);
+        assert!(embeds[1].fields[0].1.ends_with('…'));
+        assert_sendable(&Embed::from_text(Some("title"), &"z".repeat(10000)));
+        assert_sendable(&Embed::from_text(Some(&"t".repeat(300)), "text"));
+    }
+
+    #[test]
+    fn long_footer_after_full_embed() {
+        let report = Report {
+            title: Some("Session complete".to_string()),
+            header: "header".to_string(),
+            players: (0..5)
+                .map(|i| PlayerReport {
+                    heading: format!("player {}", i),
+                    stats: "y".repeat(MAX_FIELD_VALUE),
+                    win_rate: None,
+                    group: None,
+                })
+                .collect(),
+            footer: Some("f".repeat(MAX_FOOTER)),
+            win_rate: 0,
+        };
+        let embeds = Embed::from_report(&report);
+        assert_sendable(&embeds);
+        assert_eq!(embeds.len(), 2);
+        assert_eq!(embeds[0].fields.len(), 5);
+        assert!(embeds[0].footer.is_none());
+        assert!(embeds[1].fields.is_empty());
+        assert_eq!(embeds[1].footer.as_deref().map(str::len), Some(MAX_FOOTER));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{deep::DeepStats, ranked_players, win_rate, Game, PlayerStats, Settings, Tally};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::path::Path;
+
+/// A line of `--ndjson`, one for every game added to the tally. Renaming fields breaks the
+/// programs reading the stream.
+#[derive(Serialize)]
+struct GameEvent<'a> {
+    replay_file: String,
+    played_at: Option<DateTime<Local>>,
+    playlist: &'a str,
+    team_scores: (i32, i32),
+    forfeit: bool,
+    /// Games in the session including this one.
+    games_played: usize,
+    players: Vec<PlayerEvent>,
+}
+
+/// A player's part of a [`GameEvent`].
+#[derive(Serialize)]
+struct PlayerEvent {
+    /// The key of the player in the tally, see `Tally::player_stats`. The pseudonym with
+    /// `--anonymize`, as the key is their platform id or real name.
+    id: String,
+    name: String,
+    /// Null for games without a result for the player.
+    result: Option<&'static str>,
+    mvp: bool,
+    score: usize,
+    goals: usize,
+    assists: usize,
+    saves: usize,
+    shots: usize,
+    goal_differential: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deep: Option<DeepStats>,
+    /// The player's session stats after the game.
+    totals: Option<PlayerStats>,
+}
+
+/// The game as a single line of JSON, with what every player did in it and their totals in
+/// `tally` after it.
+pub fn game_event(replay: &Path, game: &Game, tally: &Tally, settings: &Settings) -> String {
+    let players = game
+        .players
+        .iter()
+        .map(|player| {
+            let (id, _) = settings.identity(player);
+            let totals = tally.player_stats.get(id).map(|stats| PlayerStats {
+                name: stats.shown_name(settings),
+                ..stats.clone()
+            });
+            let name = settings.player_name(tally, player);
+            PlayerEvent {
+                id: if settings.anonymize {
+                    name.clone()
+                } else {
+                    id.to_string()
+                },
+                name,
+                result: player.outcome.map(|outcome| outcome.as_str()),
+                mvp: player.mvp,
+                score: player.score,
+                goals: player.goals,
+                assists: player.assists,
+                saves: player.saves,
+                shots: player.shots,
+                goal_differential: player.goal_differential,
+                deep: player.deep,
+                totals,
+            }
+        })
+        .collect();
+    let event = GameEvent {
+        replay_file: replay.to_string_lossy().into_owned(),
+        played_at: game.played_at,
+        playlist: &game.playlist,
+        team_scores: game.team_scores,
+        forfeit: game.forfeit,
+        games_played: tally.games_played,
+        players,
+    };
+    serde_json::to_string(&event).expect("game events always serialize")
+}
+
+/// What `--exit-summary` writes when the session ends. Like the events, renaming fields breaks
+/// the scripts reading it.
+#[derive(Serialize)]
+struct ExitSummary {
+    games_played: usize,
+    started_at: Option<DateTime<Local>>,
+    ended_at: DateTime<Local>,
+    /// Of the tracked players, or of `--me`.
+    win_rate: usize,
+    players: Vec<ExitPlayer>,
+}
+
+/// A tracked player's session totals in an [`ExitSummary`].
+#[derive(Serialize)]
+struct ExitPlayer {
+    /// Like [`PlayerEvent::id`].
+    id: String,
+    name: String,
+    games: usize,
+    wins: usize,
+    losses: usize,
+    draws: usize,
+    mvps: usize,
+    score: usize,
+    goals: usize,
+    assists: usize,
+    saves: usize,
+    shots: usize,
+}
+
+/// The key numbers of the finished session as pretty-printed JSON, in the order the summary
+/// lists the players.
+pub fn exit_summary(tally: &Tally, settings: &Settings, now: DateTime<Local>) -> String {
+    let players = ranked_players(tally, settings)
+        .into_iter()
+        .map(|(id, stats)| ExitPlayer {
+            id: if settings.anonymize {
+                stats.pseudonym()
+            } else {
+                id.to_string()
+            },
+            name: stats.shown_name(settings),
+            games: stats.times_seen,
+            wins: stats.wins,
+            losses: stats.losses,
+            draws: stats.draws,
+            mvps: stats.mvps,
+            score: stats.score.0,
+            goals: stats.goals.0,
+            assists: stats.assists.0,
+            saves: stats.saves.0,
+            shots: stats.shots.0,
+        })
+        .collect();
+    let summary = ExitSummary {
+        games_played: tally.games_played,
+        started_at: tally.started_at,
+        ended_at: now,
+        win_rate: win_rate(tally, settings),
+        players,
+    };
+    serde_json::to_string_pretty(&summary).expect("exit summaries always serialize")
+}
@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::{
+    http::JsonClient,
+    sink::{with_retry, StatsSink},
+    template::Template,
+};
+
+/// What the body template can put in its strings.
+const PLACEHOLDERS: &[&str] = &["title", "text"];
+
+/// The body sent without `--generic-template`.
+pub const DEFAULT_BODY: &str = r#"{"title": "{title}", "text": "{text}"}"#;
+
+/// Any webhook that takes JSON, with a body of the user's making, e.g. for Mattermost.
+pub struct Generic {
+    client: JsonClient,
+    url: String,
+    /// JSON whose strings are templates, see [`fill`].
+    body: Value,
+    attempts: usize,
+}
+
+impl Generic {
+    /// Fails if `body` is not JSON or one of its strings uses a placeholder other than
+    /// `{title}` and `{text}`.
+    pub fn new(url: &str, body: &str, attempts: usize, client: JsonClient) -> Result<Self> {
+        let body: Value = serde_json::from_str(body).context("The body template is not JSON")?;
+        fill(&body, &HashMap::new()).context("The body template is not valid")?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            body,
+            attempts,
+        })
+    }
+}
+
+#[async_trait]
+impl StatsSink for Generic {
+    async fn send_message(&self, title: Option<&str>, text: &str) -> Result<()> {
+        let values = HashMap::from([
+            ("title", title.unwrap_or_default().to_string()),
+            ("text", text.to_string()),
+        ]);
+        let payload = fill(&self.body, &values)?;
+        with_retry(self.attempts, || async {
+            self.client.post(&self.url, &payload).await.map(|_| ())
+        })
+        .await
+    }
+}
+
+/// Fills in the placeholders in every string of `body`. The values end up inside JSON strings,
+/// so whatever they contain, the body stays valid JSON.
+fn fill(body: &Value, values: &HashMap<&str, String>) -> Result<Value> {
+    Ok(match body {
+        Value::String(text) => Value::String(Template::parse(text, PLACEHOLDERS)?.render(values)),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| fill(item, values))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), fill(value, values)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fill_body_template() {
+        let body = json!({
+            "username": "rl-session",
+            "props": { "card": "{title}" },
+            "attachments": [{ "text": "**{title}**\n{text}", "short": true }],
+        });
+        let values = HashMap::from([
+            ("title", "Session complete".to_string()),
+            ("text", "alice said \"gg\"\n- Goals: 3".to_string()),
+        ]);
+        assert_eq!(
+            fill(&body, &values).unwrap(),
+            json!({
+                "username": "rl-session",
+                "props": { "card": "Session complete" },
+                "attachments": [{
+                    "text": "**Session complete**\nalice said \"gg\"\n- Goals: 3",
+                    "short": true,
+                }],
+            })
+        );
+        assert!(fill(&json!({ "text": "{summary}" }), &values).is_err());
+        assert!(fill(&serde_json::from_str(DEFAULT_BODY).unwrap(), &values).is_ok());
+    }
+}
@@ -1,306 +1,4824 @@
-use anyhow::{anyhow, Result};
+mod aliases;
+mod archive;
+mod cache;
+mod chart;
+mod config;
+mod csv_out;
+mod db;
+mod deep;
+mod discord;
+mod events;
+mod generic;
+mod http;
+mod metrics;
+mod secrets;
+mod server;
+mod sink;
+mod slack;
+mod telegram;
+mod template;
+mod tui;
+mod watch;
+
+use anyhow::{anyhow, Context, Result};
 use boxcars::{HeaderProp, Replay};
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use webhook::client::WebhookClient;
+use chrono::{DateTime, Local, NaiveDateTime};
+use notify::{Config, Event, EventKind, RecommendedWatcher, Watcher};
+use serde::{Deserialize, Serialize};
 
-use clap::Parser;
+use aliases::Aliases;
+use cache::ReplayCache;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::FileConfig;
+use csv_out::CsvLog;
+use db::{Db, Lifetime};
+use deep::DeepStats;
+use discord::Discord;
+use flate2::read::GzDecoder;
+use generic::Generic;
+use globset::{GlobBuilder, GlobMatcher};
+use http::{JsonClient, Network};
 use indoc::{formatdoc, indoc};
-use std::{collections::HashMap, fs, path::PathBuf, str::FromStr};
+use secrets::Secrets;
+use sink::{escape_markdown, Broadcast, Group, PlayerReport, Report, SinkKind, StatsSink};
+use slack::Slack;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fs,
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use telegram::Telegram;
+use template::Template;
+use tracing::{debug, error, info, level_filters::LevelFilter, warn, Level};
+use tui::Tui;
+use unicode_width::UnicodeWidthStr;
+use watch::{check_location, Locations};
 
 /// A program for tracking scores while playing rocket league and publishing the running tally to discord.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Location to look for replays.
-    #[arg(short, long)]
-    location: Option<PathBuf>,
-    /// The webhook API link from Discord channel integrations.
+    /// Location to look for replays. Can be given multiple times to watch several folders.
     #[arg(short, long)]
-    webhook: Option<String>,
+    location: Vec<PathBuf>,
+    /// Also look for replays in subfolders of the locations.
+    #[arg(long)]
+    recursive: bool,
+    /// Only read replays whose file name matches this pattern, e.g. "2024-*.replay". Other
+    /// replays in the locations are ignored.
+    #[arg(long, value_name = "PATTERN", value_parser = parse_glob)]
+    replay_glob: Option<GlobMatcher>,
+    /// Create the locations that do not exist yet instead of stopping.
+    #[arg(long)]
+    create_location: bool,
+    /// The webhook API link from Discord channel integrations, or a Slack incoming webhook with --sink slack.
+    /// Read from the environment when not given, so it stays out of shell history. Can be given
+    /// multiple times to post to several channels.
+    #[arg(short, long, env = "RL_SESSION_WEBHOOK", hide_env_values = true)]
+    webhook: Vec<String>,
+    /// Which service the webhook belongs to.
+    #[arg(long, value_enum, default_value_t)]
+    sink: SinkKind,
+    /// Token of the Telegram bot to post with, for --sink telegram.
+    #[arg(long)]
+    telegram_token: Option<String>,
+    /// Id of the Telegram chat to post to, for --sink telegram.
+    #[arg(long)]
+    telegram_chat: Option<String>,
+    /// Url to post the messages to as JSON, for --sink generic.
+    #[arg(long, value_name = "URL")]
+    generic_webhook: Option<String>,
+    /// JSON file with the body to post with --sink generic. Its strings can use {title} and
+    /// {text} for the message [default: {"title": "{title}", "text": "{text}"}]
+    #[arg(long, value_name = "PATH")]
+    generic_template: Option<PathBuf>,
+    /// Name the messages are posted under on Discord [default: Rocket League Session]
+    #[arg(long)]
+    bot_name: Option<String>,
+    /// Picture to post the Discord messages with, instead of the one set for the webhook.
+    #[arg(long, value_name = "URL", value_parser = parse_image_url)]
+    avatar_url: Option<String>,
+    /// Picture to show in the corner of the Discord messages, e.g. the logo of your group.
+    #[arg(long, value_name = "URL", value_parser = parse_image_url)]
+    thumbnail_url: Option<String>,
+    /// What to call team 0 in messages, e.g. the name of your group when scrimming another.
+    #[arg(long, value_name = "NAME", default_value = "Blue")]
+    team0_name: String,
+    /// What to call team 1 in messages.
+    #[arg(long, value_name = "NAME", default_value = "Orange")]
+    team1_name: String,
+    /// Post into this thread of the Discord channel instead of the channel itself.
+    #[arg(long, value_name = "ID")]
+    thread_id: Option<u64>,
+    /// What to call the session in message titles, e.g. "Tuesday Night Rocket League" gives
+    /// "Tuesday Night Rocket League complete" [default: Session]
+    #[arg(long)]
+    session_title: Option<String>,
+    /// Do not post the explanation at the start of a session, e.g. when restarting often.
+    #[arg(long)]
+    no_start_message: bool,
+    /// Post the explanation as soon as the session starts, instead of with the first stats.
+    #[arg(long, conflicts_with = "no_start_message")]
+    eager_start_message: bool,
+    /// Show players as "Player 1", "Player 2" and so on, numbered in the order they were first
+    /// seen, instead of their names. For streaming with players who would rather not be named.
+    /// The state file and database keep the real names.
+    #[arg(long)]
+    anonymize: bool,
+    /// With --anonymize, print who is behind every pseudonym to stderr at the end of the session.
+    #[arg(long, requires = "anonymize")]
+    print_pseudonyms: bool,
     /// Run without discord and print messages to stdout.
     #[arg(short, long)]
     no_discord: bool,
+    /// How long to ignore a replay after reading it, for when it is written again right after,
+    /// e.g. "5s" or "0s" to turn it off.
+    #[arg(long, value_name = "DURATION", default_value = "5s")]
+    rewrite_cooldown: humantime::Duration,
+    /// Post a short "still tracking" message when no game came in for this long, e.g. "20m",
+    /// so the channel knows the bot is still running.
+    #[arg(long, value_name = "INTERVAL")]
+    heartbeat: Option<humantime::Duration>,
+    /// Post a bar chart of everyone's goals with the session summary. With --no-discord it is
+    /// written to session-chart.png instead.
+    #[arg(long)]
+    chart: bool,
+    /// Before watching, add replays that are already in the replay folders to the tally.
+    /// Useful if you forgot to start tracking before the first games.
+    #[arg(long)]
+    backfill: bool,
+    /// File to keep what --backfill read from every replay in, so replays that did not change
+    /// are not read again on the next start.
+    #[arg(long, value_name = "PATH", requires = "backfill")]
+    replay_cache: Option<PathBuf>,
+    /// Before watching, add the replays in this zip file to the tally, e.g. a batch a teammate
+    /// sent. They are read straight from the archive. Can be given multiple times.
+    #[arg(long, value_name = "PATH")]
+    from_zip: Vec<PathBuf>,
+    /// How old replays picked up by --backfill may be, e.g. "90m" or "6h".
+    #[arg(long, default_value = "12h")]
+    backfill_window: humantime::Duration,
+    /// Instead of watching for new replays, play every replay in this folder into the session one
+    /// after another, oldest first, as if they had just been saved. For demos and reproducing bugs.
+    #[arg(long, value_name = "DIR", conflicts_with = "backfill")]
+    simulate: Option<PathBuf>,
+    /// How long --simulate waits between replays, e.g. "500ms" or "10s".
+    #[arg(long, default_value = "2s", requires = "simulate")]
+    simulate_delay: humantime::Duration,
+    /// With --backfill, only add games played after this time. Either a date like
+    /// "2023-01-02T15:04:05+01:00" or how long ago, e.g. "2h".
+    #[arg(long, value_name = "TIME", requires = "backfill", value_parser = parse_since)]
+    since: Option<DateTime<Local>>,
+    /// How many times to try sending a message to the webhook before giving up on it.
+    #[arg(long, default_value_t = 3)]
+    send_attempts: usize,
+    /// Send to the webhook through this proxy, e.g. http://proxy.example.com:3128. Taken from
+    /// HTTPS_PROXY or ALL_PROXY when not given, leaving out the hosts in NO_PROXY.
+    #[arg(long, value_name = "URL")]
+    proxy: Option<hyper::Uri>,
+    /// Trust this PEM certificate when sending to the webhook, next to the system's, e.g. for a
+    /// proxy that looks into TLS connections.
+    #[arg(long, value_name = "PATH")]
+    ca_cert: Option<PathBuf>,
+    /// Also read the network data of replays for demolitions, boost, touches and own goals.
+    /// This is a lot slower and uses a lot more memory.
+    #[arg(long)]
+    deep_parse: bool,
+    /// Only count ranked games. Whether an online game was ranked is only in the network data,
+    /// so this needs --deep-parse. Private, offline and unknown games are left out.
+    #[arg(long, requires = "deep_parse")]
+    ranked_only: bool,
+    /// Do not post after every game, only the summary at the end of the session.
+    #[arg(long)]
+    summary_only: bool,
+    /// With --summary-only, also post the summary so far every this many games.
+    #[arg(long, value_name = "GAMES", requires = "summary_only")]
+    summary_every: Option<usize>,
+    /// State file of an earlier session to compare everyone with in the session summary, e.g.
+    /// "Score avg: 340 (+25 vs baseline)".
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<PathBuf>,
+    /// File to keep the running tally in, so a session survives restarts.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+    /// SQLite database to log every game to, for stats across sessions.
+    #[arg(long, value_name = "PATH")]
+    db: Option<PathBuf>,
+    /// CSV file to append a row per player to for every game, for spreadsheets.
+    #[arg(long, value_name = "PATH")]
+    csv_out: Option<PathBuf>,
+    /// Print everyone's lifetime stats from --db on startup.
+    #[arg(long, requires = "db")]
+    lifetime: bool,
+    /// Print a line of JSON to stdout for every game, with what everyone did in it and their
+    /// totals after it, e.g. to pipe into jq. Replaces the stats printed without a webhook.
+    #[arg(long, conflicts_with = "lifetime")]
+    ndjson: bool,
+    /// Show a live leaderboard in the terminal instead of printing the stats of every game.
+    /// Press s to change the order and q to wrap up the session. Logs are only kept when
+    /// stderr is redirected.
+    #[arg(long, conflicts_with_all = ["ndjson", "lifetime"])]
+    tui: bool,
+    /// Write the current tally as pretty-printed JSON to this file after every game.
+    #[arg(long)]
+    json_out: Option<PathBuf>,
+    /// When the session ends, write its key numbers as JSON to this file, for scripts. Exits
+    /// with an error when no games were played.
+    #[arg(long, value_name = "PATH")]
+    exit_summary: Option<PathBuf>,
+    /// Serve the live tally as JSON on http://<addr>/stats, e.g. for an OBS overlay, and as
+    /// Prometheus metrics on http://<addr>/metrics.
+    #[arg(long, value_name = "ADDR")]
+    serve: Option<String>,
+    /// Keep a separate tally for every playlist and report games against their playlist's tally.
+    #[arg(long)]
+    per_playlist: bool,
+    /// How much the latest game counts towards a player's form, their score per game weighted
+    /// towards recent games. Between 0 and 1, higher follows the last games more closely.
+    #[arg(long, value_name = "FACTOR", default_value_t = 0.3, value_parser = parse_decay)]
+    form_decay: f64,
+    /// The rating every player starts a playlist with. Ratings are Elo-style, going up with
+    /// wins and down with losses, and are no real MMR.
+    #[arg(long, value_name = "RATING", default_value_t = 1000.0)]
+    rating_base: f64,
+    /// How far a single game moves a player's rating at most.
+    #[arg(long, value_name = "K", default_value_t = 32.0)]
+    rating_k: f64,
+    /// How many recent games the trend arrow next to a player's name looks at.
+    #[arg(long, default_value_t = 5)]
+    trend_window: usize,
+    /// Games won by at most this many goals are called a nailbiter. 0 turns it off.
+    #[arg(long, value_name = "GOALS", default_value_t = 1)]
+    nailbiter_margin: u32,
+    /// Games won by at least this many goals are called a blowout. 0 turns it off.
+    #[arg(long, value_name = "GOALS", default_value_t = 5)]
+    blowout_margin: u32,
+    /// Games are guessed to be premade when at least this share of a team, and at least two
+    /// players, are regulars of the session (see --min-games). Otherwise they were likely
+    /// played with randoms.
+    #[arg(long, value_name = "SHARE", default_value_t = 0.5)]
+    premade_share: f64,
+    /// The order players are listed in. Ties are broken by name.
+    #[arg(long, value_enum, default_value_t = SortBy::default())]
+    sort_by: SortBy,
+    /// List at most this many players in the session summary, in --sort-by order, and how many
+    /// others there were after them.
+    #[arg(long, value_name = "N")]
+    max_players: Option<usize>,
+    /// List the players of the session summary under their team, with subtotals. Players go
+    /// by the team they were on in their latest game.
+    #[arg(long)]
+    group_by_team: bool,
+    /// Your own name or platform id. Games you did not play in, e.g. ones you spectated, then
+    /// still count for the players in them but not for the number of games or the win rate of
+    /// the session, which becomes yours.
+    #[arg(long, value_name = "NAME|ID")]
+    me: Option<String>,
+    /// Players show up in the report if they played every game, or more than
+    /// max(min-games, presence-ratio * games played) games.
+    #[arg(long, default_value_t = 3)]
+    min_games: usize,
+    /// See --min-games.
+    #[arg(long, default_value_t = 0.5)]
+    presence_ratio: f64,
+    /// Only show these players, by name or platform id (e.g. "Steam:76561198000000000").
+    /// Overrides --min-games and --presence-ratio.
+    #[arg(long, value_delimiter = ',')]
+    players: Vec<String>,
+    /// Leave these players out of the session, by name or platform id. They are not counted
+    /// or shown, even when they are in --players.
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+    /// File mapping players to the names and platform ids of their other accounts, whose stats
+    /// are added to theirs. See the README for the format.
+    #[arg(long, value_name = "PATH")]
+    aliases: Option<PathBuf>,
+    /// Template for every player's stats, with placeholders like {score_tally} or {wins}.
+    /// Unknown placeholders are an error.
+    #[arg(long)]
+    template: Option<String>,
+    /// Which lines of everyone's stats to show, in this order, e.g. "score,goals,wins". All of
+    /// them when not given.
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "template")]
+    columns: Vec<Column>,
+    /// Only log messages at this level or above: error, warn, info, debug or trace.
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+    /// TOML file to read default flag values from [default: rl-session.toml]
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// TOML file, or JSON when it ends in .json, with the `webhook` and `telegram_token` to use
+    /// over any given elsewhere. Only its owner may be able to read it.
+    #[arg(long, value_name = "PATH")]
+    secrets: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Read a single replay and print what was found in it, without posting or watching anything.
+    Validate {
+        /// The replay to read.
+        path: PathBuf,
+    },
+}
+
+impl Args {
+    /// Whether the stats of games go to stdout when there is no sink. `--ndjson` and `--tui`
+    /// take stdout over.
+    fn prints_stats(&self) -> bool {
+        !self.ndjson && !self.tui
+    }
+
+    /// Title of a message about the whole session, e.g. "Session complete".
+    fn session_title(&self, what: &str) -> String {
+        format!(
+            "{} {}",
+            self.session_title.as_deref().unwrap_or("Session"),
+            what
+        )
+    }
+
+    /// Fills in anything not given on the command line from the config file.
+    fn merge(&mut self, config: FileConfig) {
+        if self.location.is_empty() {
+            self.location = config.location.map(Vec::from).unwrap_or_default();
+        }
+        if self.webhook.is_empty() {
+            self.webhook.extend(config.webhook);
+        }
+        self.no_discord |= config.no_discord.unwrap_or_default();
+        self.no_start_message |= config.no_start_message.unwrap_or_default();
+        self.eager_start_message |= config.eager_start_message.unwrap_or_default();
+        // Columns on the command line win over a template in the config file, and the other
+        // way around.
+        if self.columns.is_empty() && self.template.is_none() {
+            self.columns = config.columns.unwrap_or_default();
+        }
+        self.template = self.template.take().or(config.template);
+        self.bot_name = self.bot_name.take().or(config.bot_name);
+        self.session_title = self.session_title.take().or(config.session_title);
+        self.secrets = self.secrets.take().or(config.secrets);
+    }
+
+    /// Puts the credentials from the secrets file over whatever was given elsewhere.
+    fn merge_secrets(&mut self, secrets: Secrets) {
+        if !secrets.webhook.is_empty() {
+            self.webhook = secrets.webhook;
+        }
+        self.telegram_token = secrets.telegram_token.or(self.telegram_token.take());
+    }
 }
 
-#[derive(Debug)]
+/// The running tally for the session. This is also the format of `--state-file` and `--json-out`,
+/// so renaming fields here breaks external tooling.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 struct Tally {
+    /// Stats keyed by platform id, or by name for players without one.
     player_stats: HashMap<String, PlayerStats>,
     games_played: usize,
+    /// Tallies for every playlist seen, only kept with `--per-playlist`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    playlists: HashMap<String, Tally>,
+    /// Every game in the order they were added.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<TeamGame>,
+    /// When the session started, kept so resuming from a state file does not restart the clock.
+    /// Only set on the session's own tally, not the ones per playlist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started_at: Option<DateTime<Local>>,
+    /// Ids of the replays counted so far, so a copied replay or a repeated file event does not
+    /// count a game twice, also not after a restart. Only kept on the session's own tally.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    seen: BTreeSet<String>,
+}
+
+/// What the tally keeps of every game as a whole.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct TeamGame {
+    /// Goals of team 0 and team 1.
+    team_scores: (i32, i32),
+    /// Seconds played, including overtime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<f32>,
+}
+
+impl Tally {
+    /// Remembers the game's replay, returning whether it is new. Replays without an id are
+    /// always new.
+    fn first_seen(&mut self, game: &Game) -> bool {
+        game.replay_id
+            .as_ref()
+            .is_none_or(|id| self.seen.insert(id.clone()))
+    }
+
+    /// Adds a game to the tally, and to its playlist's tally with `--per-playlist`.
+    fn add_game(&mut self, game: &Game, settings: &Settings) {
+        self.accumulate(game, settings);
+        if settings.per_playlist {
+            let playlist = self.playlists.entry(game.playlist.clone()).or_default();
+            playlist.accumulate(game, settings);
+            // Players keep the pseudonym they have in the session, whichever playlist they are in.
+            for (key, stats) in &mut playlist.player_stats {
+                if let Some(session) = self.player_stats.get(key) {
+                    stats.first_seen = session.first_seen;
+                }
+            }
+        }
+    }
+
+    fn accumulate(&mut self, game: &Game, settings: &Settings) {
+        for player in game.players.iter().filter(|p| !settings.excludes(p)) {
+            let PlayerGame {
+                id: _,
+                name: _,
+                score,
+                goals,
+                assists,
+                saves,
+                shots,
+                goal_differential,
+                outcome,
+                team,
+                mvp,
+                deep,
+                rank,
+            } = *player;
+            let won = outcome == Some(Outcome::Win);
+            let lost = outcome == Some(Outcome::Loss);
+            let drew = outcome == Some(Outcome::Draw);
+            let (id, name) = settings.identity(player);
+            let first_seen = self.player_stats.len() + 1;
+            let stats = self
+                .player_stats
+                .entry(id.to_string())
+                .and_modify(|stats| {
+                    name.clone_into(&mut stats.name);
+                    stats.times_seen += 1;
+                    stats.wins += won as usize;
+                    stats.losses += lost as usize;
+                    stats.draws += drew as usize;
+                    stats.mvps += mvp as usize;
+                    stats.score = (stats.score.0 + score, score);
+                    stats.goals = (stats.goals.0 + goals, goals);
+                    stats.assists = (stats.assists.0 + assists, assists);
+                    stats.saves = (stats.saves.0 + saves, saves);
+                    stats.shots = (stats.shots.0 + shots, shots);
+                    stats.goal_differential = (
+                        stats.goal_differential.0 + goal_differential,
+                        goal_differential,
+                    );
+                    stats.best.score = stats.best.score.max(score);
+                    stats.best.goals = stats.best.goals.max(goals);
+                    stats.best.assists = stats.best.assists.max(assists);
+                    stats.best.saves = stats.best.saves.max(saves);
+                    stats.form = Some(match stats.form {
+                        Some(form) => settings.form_decay.mul_add(score as f64 - form, form),
+                        None => score as f64,
+                    });
+                    if let Some(deep) = deep {
+                        let mut total = stats.deep.map(|(total, _)| total).unwrap_or_default();
+                        total.add(&deep);
+                        stats.deep = Some((total, deep));
+                    }
+                })
+                .or_insert(PlayerStats {
+                    name: name.to_string(),
+                    recent: VecDeque::new(),
+                    times_seen: 1,
+                    score: (score, score),
+                    goals: (goals, goals),
+                    assists: (assists, assists),
+                    saves: (saves, saves),
+                    shots: (shots, shots),
+                    goal_differential: (goal_differential, goal_differential),
+                    best: Best {
+                        score,
+                        goals,
+                        assists,
+                        saves,
+                    },
+                    wins: won as usize,
+                    losses: lost as usize,
+                    draws: drew as usize,
+                    mvps: mvp as usize,
+                    streak: 0,
+                    form: Some(score as f64),
+                    ranks: BTreeMap::new(),
+                    team,
+                    ratings: BTreeMap::new(),
+                    first_seen,
+                    deep: deep.map(|deep| (deep, deep)),
+                });
+            stats.team = team;
+            if let Some(rank) = rank {
+                let before = stats
+                    .ranks
+                    .get(&game.playlist)
+                    .map_or(rank, |(now, _)| *now);
+                stats.ranks.insert(game.playlist.clone(), (rank, before));
+            }
+            if let Some(outcome) = outcome {
+                let (base, _) = settings.rating;
+                stats
+                    .ratings
+                    .entry(game.playlist.clone())
+                    .or_insert(Rating {
+                        rating: base,
+                        delta: 0.0,
+                    })
+                    .update(outcome, settings.rating);
+            }
+            // A draw neither ends nor extends a streak.
+            if won {
+                stats.streak = stats.streak.max(0) + 1;
+            } else if lost {
+                stats.streak = stats.streak.min(0) - 1;
+            }
+            if won || lost {
+                stats.recent.push_back(won);
+                while stats.recent.len() > settings.trend_window {
+                    stats.recent.pop_front();
+                }
+            }
+        }
+        // With --me, games someone else played are only counted for the players in them.
+        if !settings.plays_in(game) {
+            return;
+        }
+        self.history.push(TeamGame {
+            team_scores: game.team_scores,
+            duration: game.duration,
+        });
+        self.games_played += 1;
+    }
+
+    /// Starts the session over at `now`. Only the replays counted so far are remembered, so they
+    /// are not counted again.
+    fn reset(&mut self, now: DateTime<Local>) {
+        *self = Tally {
+            seen: std::mem::take(&mut self.seen),
+            started_at: Some(now),
+            ..Tally::default()
+        };
+    }
+
+    /// Average length in seconds of the games whose replay said how long they took.
+    fn average_duration(&self) -> Option<f32> {
+        let durations: Vec<f32> = self
+            .history
+            .iter()
+            .filter_map(|game| game.duration)
+            .collect();
+        if durations.is_empty() {
+            return None;
+        }
+        Some(durations.iter().sum::<f32>() / durations.len() as f32)
+    }
+
+    /// State files from before players were keyed by id have no names, but use the name as key.
+    fn fill_missing_names(&mut self) {
+        for (key, stats) in &mut self.player_stats {
+            if stats.name.is_empty() {
+                stats.name.clone_from(key);
+            }
+        }
+        for tally in self.playlists.values_mut() {
+            tally.fill_missing_names();
+        }
+    }
+
+    /// State files from before `--anonymize` do not say in which order players were first seen,
+    /// so those players are numbered after everyone else, by key to keep it the same every time.
+    fn number_players(&mut self) {
+        let mut next = self
+            .player_stats
+            .values()
+            .map(|stats| stats.first_seen)
+            .max()
+            .unwrap_or_default();
+        let mut unnumbered: Vec<_> = self
+            .player_stats
+            .iter_mut()
+            .filter(|(_, stats)| stats.first_seen == 0)
+            .collect();
+        unnumbered.sort_unstable_by_key(|(key, _)| *key);
+        for (_, stats) in unnumbered {
+            next += 1;
+            stats.first_seen = next;
+        }
+        for tally in self.playlists.values_mut() {
+            for (key, stats) in &mut tally.player_stats {
+                if let Some(session) = self.player_stats.get(key) {
+                    stats.first_seen = session.first_seen;
+                }
+            }
+        }
+    }
+
+    /// A copy with every player's name replaced by their pseudonym, for output of `--anonymize`.
+    /// Players are keyed by their pseudonym too, as the key is their platform id or real name.
+    fn anonymized(&self) -> Tally {
+        let anonymize = |player_stats: &HashMap<String, PlayerStats>| {
+            player_stats
+                .values()
+                .map(|stats| {
+                    let pseudonym = stats.pseudonym();
+                    let stats = PlayerStats {
+                        name: pseudonym.clone(),
+                        ..stats.clone()
+                    };
+                    (pseudonym, stats)
+                })
+                .collect()
+        };
+        let mut tally = self.clone();
+        tally.player_stats = anonymize(&self.player_stats);
+        for (playlist, t) in &mut tally.playlists {
+            t.player_stats = anonymize(&self.playlists[playlist].player_stats);
+        }
+        tally
+    }
 }
 
-#[derive(Debug)]
+/// Stats for a single player. The tuples are `(accumulated, last game)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 struct PlayerStats {
+    /// The name the player had in the latest game.
+    #[serde(default)]
+    name: String,
     times_seen: usize,
     wins: usize,
     losses: usize,
+    #[serde(default)]
+    draws: usize,
+    #[serde(default)]
+    mvps: usize,
     score: (usize, usize),
     goals: (usize, usize),
     assists: (usize, usize),
     saves: (usize, usize),
     shots: (usize, usize),
+    /// Goals scored minus goals conceded by the player's team.
+    #[serde(default)]
+    goal_differential: (i64, i64),
+    #[serde(default)]
+    best: Best,
+    /// Whether the player won their last few games with a result, oldest first, see `--trend-window`.
+    #[serde(default)]
+    recent: VecDeque<bool>,
+    /// Games won in a row when positive, lost in a row when negative.
+    #[serde(default)]
+    streak: i64,
+    /// Score per game weighted towards recent games, see `--form-decay`. Nothing for state
+    /// files from before it was kept, until the player's next game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    form: Option<f64>,
+    /// The rank tier in every playlist the player was seen with one, and the tier before their
+    /// last game there, see [`deep::skill_tiers`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ranks: BTreeMap<String, (u8, u8)>,
+    /// 0 for blue and 1 for orange in the latest game the player was in, if the replay said.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    team: Option<usize>,
+    /// Elo-style rating in every playlist the player played, see `--rating-base`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ratings: BTreeMap<String, Rating>,
+    /// The player was the n-th one seen this session, counting from 1. Gives their pseudonym.
+    #[serde(default)]
+    first_seen: usize,
+    /// Only there for players seen in a game read with `--deep-parse`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deep: Option<(DeepStats, DeepStats)>,
+}
+
+impl PlayerStats {
+    /// What the player is called with `--anonymize`, e.g. "Player 2".
+    fn pseudonym(&self) -> String {
+        format!("Player {}", self.first_seen)
+    }
+
+    /// The name the player is shown with.
+    fn shown_name(&self, settings: &Settings) -> String {
+        if settings.anonymize {
+            self.pseudonym()
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Percentage of shots that went in over the session.
+    fn shot_accuracy(&self) -> usize {
+        (self.goals.0 * 100)
+            .checked_div(self.shots.0)
+            .unwrap_or_default()
+    }
+
+    /// Average of an accumulated stat over the games the player was seen in, rounded.
+    fn per_game(&self, total: usize) -> usize {
+        if self.times_seen == 0 {
+            return 0;
+        }
+        (total as f64 / self.times_seen as f64).round() as usize
+    }
+
+    /// Percentage of games with a result that were won, nothing before the first one.
+    fn win_rate(&self) -> Option<usize> {
+        (self.wins * 100).checked_div(self.wins + self.losses)
+    }
+
+    /// Whether the recent games went better or worse than the session as a whole.
+    /// Nothing until the player has a full window of results.
+    fn trend(&self, trend_window: usize) -> Option<&'static str> {
+        if trend_window == 0 || self.recent.len() < trend_window {
+            return None;
+        }
+        let recent_wins = self.recent.iter().filter(|won| **won).count();
+        let recent = (recent_wins * 100 / self.recent.len()) as i64;
+        let overall = ((self.wins * 100) / (self.wins + self.losses)) as i64;
+        Some(match recent - overall {
+            diff if diff > TREND_MARGIN => "↑",
+            diff if diff < -TREND_MARGIN => "↓",
+            _ => "→",
+        })
+    }
+
+    /// The current streak, e.g. "🔥 W3" or "❄️ L2". Nothing for a single game, that is no streak yet.
+    fn streak(&self) -> Option<String> {
+        match self.streak {
+            wins if wins >= MIN_STREAK => Some(format!("🔥 W{wins}")),
+            losses if losses <= -MIN_STREAK => Some(format!("❄️ L{}", -losses)),
+            _ => None,
+        }
+    }
+}
+
+/// How many wins or losses in a row it takes to show a streak.
+const MIN_STREAK: i64 = 2;
+
+/// How many percentage points the recent win rate has to differ from the overall one to count as a trend.
+const TREND_MARGIN: i64 = 10;
+
+/// The best a player did in a single game of the session.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct Best {
+    score: usize,
+    goals: usize,
+    assists: usize,
+    saves: usize,
+}
+
+/// A player's rating in a playlist, see `--rating-base`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct Rating {
+    rating: f64,
+    /// How much the last game in the playlist moved it.
+    delta: f64,
+}
+
+impl Rating {
+    /// Moves the rating by a game with `outcome`, Elo-style against an opponent at the
+    /// starting rating, as we do not know how good the other team was.
+    fn update(&mut self, outcome: Outcome, (base, k): (f64, f64)) {
+        let result = match outcome {
+            Outcome::Win => 1.0,
+            Outcome::Draw => 0.5,
+            Outcome::Loss => 0.0,
+        };
+        let expected = 1.0 / (1.0 + 10f64.powf((base - self.rating) / 400.0));
+        self.delta = k * (result - expected);
+        self.rating += self.delta;
+    }
+}
+
+/// Everything we take from a single replay.
+/// Also what `--replay-cache` keeps of a replay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Game {
+    /// Human readable playlist, e.g. "Online 3v3", or "Unknown" if the replay does not say.
+    playlist: String,
+    forfeit: bool,
+    /// Goals of team 0 and team 1.
+    team_scores: (i32, i32),
+    /// When the match was played, from the replay or else the time the file was last written.
+    played_at: Option<DateTime<Local>>,
+    /// Seconds played, including overtime, if the replay says.
+    duration: Option<f32>,
+    /// The replay did not say who the MVP was, so we picked one.
+    mvp_guessed: bool,
+    /// Whether the game counted towards a rank, if we could tell, see [`deep::ranked`].
+    ranked: Option<bool>,
+    /// The id in the replay's header, the same for every copy of the file.
+    replay_id: Option<String>,
+    players: Vec<PlayerGame>,
+}
+
+/// A single player's stats from one game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlayerGame {
+    /// Stable identifier for the player, see [`player_id`].
+    id: String,
+    name: String,
+    score: usize,
+    goals: usize,
+    assists: usize,
+    saves: usize,
+    shots: usize,
+    goal_differential: i64,
+    /// Nothing if the game has no result for the player, see [`outcome`].
+    outcome: Option<Outcome>,
+    /// 0 for blue and 1 for orange, nothing when the replay does not say.
+    #[serde(default)]
+    team: Option<usize>,
+    mvp: bool,
+    deep: Option<DeepStats>,
+    /// The rank tier the player was shown with, see [`deep::skill_tiers`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rank: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Outcome {
+    /// How the outcome is written in the database and the csv output.
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Win => "win",
+            Outcome::Loss => "loss",
+            Outcome::Draw => "draw",
+        }
+    }
+}
+
+/// Decides which players are part of the session, see `--min-games` and `--players`.
+#[derive(Debug, Clone)]
+struct Presence {
+    min_games: usize,
+    ratio: f64,
+    /// Lowercased names or ids from `--players`. When given, these are the only players shown.
+    players: Vec<String>,
+}
+
+impl Presence {
+    fn is_present(&self, id: &str, stats: &PlayerStats, games_played: usize) -> bool {
+        if !self.players.is_empty() {
+            return self.players.iter().any(|player| {
+                *player == stats.name.to_lowercase() || *player == id.to_lowercase()
+            });
+        }
+        let threshold = (games_played as f64 * self.ratio) as usize;
+        stats.times_seen == games_played || stats.times_seen > usize::max(self.min_games, threshold)
+    }
+
+    /// How many more games the player has to play to show up, if they play every game from
+    /// now on. Nothing when they never would, or when `--players` decides who shows up.
+    fn games_needed(&self, id: &str, stats: &PlayerStats, games_played: usize) -> Option<usize> {
+        if !self.players.is_empty() {
+            return None;
+        }
+        // With a ratio of 1 or more, nobody who missed a game catches up, so give up at some point.
+        (1..=MAX_GAMES_NEEDED).find(|more| {
+            let stats = PlayerStats {
+                times_seen: stats.times_seen + more,
+                ..stats.clone()
+            };
+            self.is_present(id, &stats, games_played + more)
+        })
+    }
+}
+
+/// Whether a game was played with the usual group or with randoms, see [`guess_party`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Party {
+    Premade,
+    Solo,
+}
+
+impl Party {
+    /// It is only a guess, and says so.
+    fn as_str(self) -> &'static str {
+        match self {
+            Party::Premade => "likely premade",
+            Party::Solo => "likely solo",
+        }
+    }
+}
+
+/// Games it takes before it is clear who keeps showing up, and so who the group is.
+const MIN_GAMES_FOR_PARTY: usize = 3;
+
+/// Guesses whether the game in `tally` was played as a premade group or with randoms. The
+/// group is the players that are part of the session, see [`Presence`], and a game is premade
+/// when at least two of them and at least `--premade-share` of a team were in the group.
+/// Nothing for 1v1s, games without teams, and early in the session.
+fn guess_party(tally: &Tally, game: &Game, settings: &Settings) -> Option<Party> {
+    if tally.games_played < MIN_GAMES_FOR_PARTY {
+        return None;
+    }
+    let group: BTreeSet<&str> = tracked_players(tally, &settings.presence)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    // Players and players of the group per team.
+    let mut teams: HashMap<usize, (usize, usize)> = HashMap::new();
+    for player in &game.players {
+        let Some(team) = player.team else {
+            continue;
+        };
+        let (players, in_group) = teams.entry(team).or_default();
+        *players += 1;
+        *in_group += group.contains(settings.identity(player).0) as usize;
+    }
+    let (players, in_group) = teams.into_values().max_by_key(|(_, in_group)| *in_group)?;
+    if players < 2 {
+        return None;
+    }
+    let premade = in_group >= 2 && in_group as f64 >= players as f64 * settings.premade_share;
+    Some(if premade { Party::Premade } else { Party::Solo })
+}
+
+/// Players that need more games than this to show up are not mentioned as almost there.
+const MAX_GAMES_NEEDED: usize = 10;
+
+/// The order players are listed in, see `--sort-by`. Players that tie are ordered by name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum SortBy {
+    /// Highest session score first.
+    #[default]
+    Score,
+    /// Most session goals first.
+    Goals,
+    /// Most wins first.
+    Wins,
+    /// Alphabetically, ignoring case.
+    Name,
+}
+
+impl SortBy {
+    /// With `anonymize`, players go by their pseudonyms, so those decide the order of names.
+    fn compare(self, a: &PlayerStats, b: &PlayerStats, anonymize: bool) -> std::cmp::Ordering {
+        let by_name = || {
+            if anonymize {
+                return a.first_seen.cmp(&b.first_seen);
+            }
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.name.cmp(&b.name))
+        };
+        match self {
+            SortBy::Score => b.score.0.cmp(&a.score.0).then_with(by_name),
+            SortBy::Goals => b.goals.0.cmp(&a.goals.0).then_with(by_name),
+            SortBy::Wins => b.wins.cmp(&a.wins).then_with(by_name),
+            SortBy::Name => by_name(),
+        }
+    }
+
+    /// The order after this one, going round, for switching between them in the TUI.
+    fn next(self) -> Self {
+        let all = Self::value_variants();
+        let index = all
+            .iter()
+            .position(|sort_by| *sort_by == self)
+            .unwrap_or_default();
+        all[(index + 1) % all.len()]
+    }
+}
+
+/// How games are counted and shown, from the command line.
+#[derive(Debug, Clone)]
+struct Settings {
+    per_playlist: bool,
+    /// How many recent results players remember for their trend, see `--trend-window`.
+    trend_window: usize,
+    /// Goal differences for a nailbiter and a blowout, see [`Settings::margin`].
+    margins: (u32, u32),
+    /// Share of a team that has to be in the group for a premade, see [`guess_party`].
+    premade_share: f64,
+    presence: Presence,
+    sort_by: SortBy,
+    /// How every player's stats are shown, see `--template`.
+    template: Template,
+    aliases: Aliases,
+    /// Leave out games that were not ranked, see `--ranked-only`.
+    ranked_only: bool,
+    /// Whether replays are read with their network data, see `--deep-parse`.
+    deep_parse: bool,
+    /// What teams 0 and 1 are called, see `--team0-name`.
+    team_names: (String, String),
+    /// Show pseudonyms instead of names, see `--anonymize`.
+    anonymize: bool,
+    /// An earlier session to compare the summary with, see `--baseline`.
+    baseline: Option<Tally>,
+    /// How many players the summary lists at most, see `--max-players`.
+    max_players: Option<usize>,
+    /// List the summary's players under their team, see `--group-by-team`.
+    group_by_team: bool,
+    /// Lowercased name or id of whoever runs the session, see `--me`.
+    me: Option<String>,
+    /// Lowercased names or ids of players left out, see `--exclude`.
+    exclude: Vec<String>,
+    /// How much the latest game counts towards a player's form, see `--form-decay`.
+    form_decay: f64,
+    /// The starting rating and K-factor, see `--rating-base` and `--rating-k`.
+    rating: (f64, f64),
+}
+
+impl Settings {
+    /// The key and name a player is kept under in the tally. Players with several accounts are
+    /// kept under their canonical name from `--aliases`.
+    fn identity<'a>(&'a self, player: &'a PlayerGame) -> (&'a str, &'a str) {
+        match self.aliases.resolve(&player.id, &player.name) {
+            Some(canonical) => (canonical, canonical),
+            None => (&player.id, &player.name),
+        }
+    }
+
+    /// Whether the player is the one running the session, see `--me`.
+    fn is_me(&self, id: &str, name: &str) -> bool {
+        self.me
+            .as_ref()
+            .is_some_and(|me| *me == id.to_lowercase() || *me == name.to_lowercase())
+    }
+
+    /// Whether the player is left out of the session, see `--exclude`.
+    fn excludes(&self, player: &PlayerGame) -> bool {
+        let (key, name) = self.identity(player);
+        [player.id.as_str(), &player.name, key, name]
+            .iter()
+            .any(|id| self.exclude.contains(&id.to_lowercase()))
+    }
+
+    /// Whether the game counts for the session as a whole, which without `--me` every game does.
+    fn plays_in(&self, game: &Game) -> bool {
+        self.me.is_none()
+            || game.players.iter().any(|player| {
+                let (key, name) = self.identity(player);
+                self.is_me(&player.id, &player.name) || self.is_me(key, name)
+            })
+    }
+
+    /// The name a player of a game is shown with, their pseudonym in `tally` with `--anonymize`.
+    fn player_name(&self, tally: &Tally, player: &PlayerGame) -> String {
+        if !self.anonymize {
+            return player.name.clone();
+        }
+        match tally.player_stats.get(self.identity(player).0) {
+            Some(stats) => stats.pseudonym(),
+            None => "Player ?".to_string(),
+        }
+    }
+
+    /// The score of a game with the team names, e.g. "Blue 3-1 Orange".
+    fn score(&self, game: &Game) -> String {
+        format!(
+            "{} {}-{} {}",
+            self.team_names.0, game.team_scores.0, game.team_scores.1, self.team_names.1
+        )
+    }
+
+    /// What the game was like going by the goal difference, see `--nailbiter-margin` and
+    /// `--blowout-margin`. Draws are neither.
+    fn margin(&self, game: &Game) -> Option<&'static str> {
+        let (nailbiter, blowout) = self.margins;
+        match game.team_scores.0.abs_diff(game.team_scores.1) {
+            0 => None,
+            goals if goals <= nailbiter => Some("nailbiter"),
+            goals if blowout > 0 && goals >= blowout => Some("blowout"),
+            _ => None,
+        }
+    }
+
+    /// The forfeit, margin and party of a game in `tally`, e.g. "forfeit, blowout", if it has
+    /// any of them.
+    fn game_tags(&self, tally: &Tally, game: &Game) -> Option<String> {
+        let tags: Vec<&str> = game
+            .forfeit
+            .then_some("forfeit")
+            .into_iter()
+            .chain(self.margin(game))
+            .chain(guess_party(tally, game, self).map(Party::as_str))
+            .collect();
+        (!tags.is_empty()).then(|| tags.join(", "))
+    }
+
+    /// Whether the game goes into the tally. Games that do not are logged with the reason.
+    fn counts(&self, game: &Game) -> bool {
+        match game.ranked {
+            Some(false) if self.ranked_only => {
+                debug!("Leaving out {} game, it was not ranked", game.playlist);
+                false
+            }
+            None if self.ranked_only => {
+                debug!(
+                    "Leaving out {} game, the replay does not say if it was ranked",
+                    game.playlist
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Replays that are still being written, with the time of the last file event for each.
+#[derive(Debug, Default)]
+struct PendingReplays {
+    last_event: HashMap<PathBuf, Instant>,
+    /// When replays were last read, to ignore them being written again within `cooldown`.
+    read_at: HashMap<PathBuf, Instant>,
+    cooldown: Duration,
+    /// How often replays that were not ready yet have been tried again.
+    retries: HashMap<PathBuf, usize>,
+}
+
+impl PendingReplays {
+    fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            ..Default::default()
+        }
+    }
+
+    /// Starts waiting for the replay to be written, unless it was read within the cooldown.
+    /// Bakkesmod sometimes writes a replay again right after, which is the same game.
+    fn created(&mut self, path: PathBuf, now: Instant) {
+        let cooling = self
+            .read_at
+            .get(&path)
+            .is_some_and(|read_at| now.saturating_duration_since(*read_at) < self.cooldown);
+        if cooling {
+            debug!(
+                "Ignoring {}, it was read moments ago",
+                path.to_string_lossy()
+            );
+            return;
+        }
+        self.last_event.insert(path, now);
+    }
+
+    /// Pushes back when the replay is read. Files we did not see created are ignored.
+    fn modified(&mut self, path: &Path, now: Instant) {
+        if let Some(last_event) = self.last_event.get_mut(path) {
+            *last_event = now;
+        }
+    }
+
+    /// Forgets a replay that was deleted before it settled. Returns whether it was pending.
+    fn removed(&mut self, path: &Path) -> bool {
+        self.last_event.remove(path).is_some()
+    }
+
+    /// When the next replay settles, if any are pending.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.last_event
+            .values()
+            .min()
+            .map(|oldest| *oldest + SETTLE_TIME)
+    }
+
+    /// Waits for the replay to settle again, when it could not be read yet. Returns false once
+    /// it has been tried `MAX_READ_RETRIES` times, to give up on it.
+    fn retry(&mut self, path: PathBuf, now: Instant) -> bool {
+        let retries = self.retries.entry(path.clone()).or_default();
+        if *retries >= MAX_READ_RETRIES {
+            self.retries.remove(&path);
+            return false;
+        }
+        *retries += 1;
+        self.last_event.insert(path, now);
+        true
+    }
+
+    /// Removes and returns the replays that have gone `SETTLE_TIME` without events, oldest first.
+    fn take_settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        // Replays that are no longer pending were read or given up on by now.
+        let last_event = &self.last_event;
+        self.retries.retain(|p, _| last_event.contains_key(p));
+        let mut settled: Vec<(PathBuf, Instant)> = self
+            .last_event
+            .iter()
+            .filter(|(_, last_event)| now.saturating_duration_since(**last_event) >= SETTLE_TIME)
+            .map(|(p, last_event)| (p.clone(), *last_event))
+            .collect();
+        settled.sort_unstable_by_key(|(_, last_event)| *last_event);
+        let cooldown = self.cooldown;
+        self.read_at
+            .retain(|_, read_at| now.saturating_duration_since(*read_at) < cooldown);
+        for (p, _) in &settled {
+            self.last_event.remove(p);
+            self.read_at.insert(p.clone(), now);
+        }
+        settled.into_iter().map(|(p, _)| p).collect()
+    }
+}
+
+/// What the main loop waits on.
+enum Message {
+    Fs(notify::Result<Event>),
+    /// A replay that is already written, from `--simulate`.
+    Replay(PathBuf),
+    /// Start the session over, from POST /reset on the stats server.
+    Reset,
+    /// Ctrl-C was pressed, wrap up the session.
+    Shutdown,
 }
 
 const BOT_NAME: &str = "Rocket League Session";
 
+/// How long a replay must go without file events before we consider it written.
+const SETTLE_TIME: Duration = Duration::from_millis(1500);
+
+/// How often to try reading a replay again that is still locked or incomplete, `SETTLE_TIME`
+/// apart, before skipping it.
+const MAX_READ_RETRIES: usize = 5;
+
+/// Where the session chart goes with `--chart` but without a sink.
+const CHART_FILE: &str = "session-chart.png";
+
+/// The least time between two game messages. Games finishing within it are posted together.
+const SEND_INTERVAL: Duration = Duration::from_secs(5);
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    // Logs go to stderr, stdout only has the stats when running without a webhook. The TUI has
+    // the whole terminal, so they are dropped unless stderr goes somewhere else.
+    let log_level = if args.tui && std::io::stderr().is_terminal() {
+        LevelFilter::OFF
+    } else {
+        LevelFilter::from_level(args.log_level)
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(log_level)
+        .with_writer(std::io::stderr)
+        .init();
+    let config = FileConfig::load(args.config.as_deref())?;
+    args.merge(config);
+    if let Some(path) = &args.secrets {
+        let secrets = Secrets::load(path)?;
+        args.merge_secrets(secrets);
+    }
 
-    if args.webhook.is_none() && !args.no_discord {
-        return Err(anyhow!(
-            "You must either provide a webhook with --webhook or run with --no-discord"
-        ));
+    if let Some(Command::Validate { path }) = &args.command {
+        let replay = parse_rl(path, args.deep_parse)
+            .with_context(|| format!("Could not read replay {}", path.to_string_lossy()))?;
+        match read_game(&replay)? {
+            Some(game) => print!(
+                "{}",
+                render_validation(&game, (&args.team0_name, &args.team1_name))
+            ),
+            None => println!("Not a match (e.g. freeplay or workshop), nothing would be counted"),
+        }
+        return Ok(());
+    }
+
+    if !args.no_discord {
+        match args.sink {
+            SinkKind::Telegram if args.telegram_token.is_none() || args.telegram_chat.is_none() => {
+                return Err(anyhow!(
+                    "You must either provide --telegram-token and --telegram-chat or run with --no-discord"
+                ));
+            }
+            SinkKind::Generic if args.generic_webhook.is_none() => {
+                return Err(anyhow!(
+                    "You must either provide --generic-webhook or run with --no-discord"
+                ));
+            }
+            SinkKind::Slack | SinkKind::Telegram | SinkKind::Generic
+                if args.thread_id.is_some() =>
+            {
+                return Err(anyhow!("--thread-id only works with Discord"));
+            }
+            // A thread belongs to one channel, so the other webhooks could not post into it.
+            SinkKind::Discord if args.thread_id.is_some() && args.webhook.len() > 1 => {
+                return Err(anyhow!("--thread-id only works with a single webhook"));
+            }
+            SinkKind::Slack | SinkKind::Telegram | SinkKind::Generic
+                if args.avatar_url.is_some() || args.thumbnail_url.is_some() =>
+            {
+                return Err(anyhow!(
+                    "--avatar-url and --thumbnail-url only work with Discord"
+                ));
+            }
+            SinkKind::Slack | SinkKind::Telegram | SinkKind::Generic if args.chart => {
+                return Err(anyhow!("--chart only works with Discord or --no-discord"));
+            }
+            SinkKind::Discord | SinkKind::Slack if args.webhook.is_empty() => {
+                return Err(anyhow!(
+                    "You must either provide a webhook with --webhook or RL_SESSION_WEBHOOK, or run with --no-discord"
+                ));
+            }
+            _ => {}
+        }
     }
 
-    let client: WebhookClient = WebhookClient::new(&args.webhook.unwrap_or_default());
+    let settings = Settings {
+        per_playlist: args.per_playlist,
+        trend_window: args.trend_window,
+        margins: (args.nailbiter_margin, args.blowout_margin),
+        premade_share: args.premade_share,
+        sort_by: args.sort_by,
+        presence: Presence {
+            min_games: args.min_games,
+            ratio: args.presence_ratio,
+            players: args.players.iter().map(|p| p.to_lowercase()).collect(),
+        },
+        template: match (&args.template, args.columns.as_slice()) {
+            (Some(template), []) => Template::parse(template, PLAYER_PLACEHOLDERS)?,
+            (_, columns) => Template::parse(&columns_template(columns), PLAYER_PLACEHOLDERS)?,
+        },
+        aliases: match &args.aliases {
+            Some(path) => Aliases::load(path)?,
+            None => Aliases::default(),
+        },
+        ranked_only: args.ranked_only,
+        deep_parse: args.deep_parse,
+        team_names: (args.team0_name.clone(), args.team1_name.clone()),
+        anonymize: args.anonymize,
+        baseline: match &args.baseline {
+            Some(path) => Some(load_tally(path)?),
+            None => None,
+        },
+        max_players: args.max_players,
+        group_by_team: args.group_by_team,
+        me: args.me.as_ref().map(|me| me.to_lowercase()),
+        exclude: args.exclude.iter().map(|p| p.to_lowercase()).collect(),
+        form_decay: args.form_decay,
+        rating: (args.rating_base, args.rating_k),
+    };
 
-    let Some(location) = args.location.or_else(|| PathBuf::from_str(&format!(r"C:\Users\{}\AppData\Roaming\bakkesmod\bakkesmod\data\replays", whoami::username())).ok()) else {
-        return Err(anyhow!("Location was not valid and default location did not work. Please supply a path to the replay folder"));
+    // Without a sink the stats are printed, styled for the terminal unless stdout is piped somewhere.
+    let color = std::io::stdout().is_terminal();
+    let client = JsonClient::new(&Network::from_env(
+        args.proxy.clone(),
+        args.ca_cert.clone(),
+    )?)?;
+    let mut sinks: Vec<Box<dyn StatsSink>> = match (args.no_discord, args.sink) {
+        (true, _) => Vec::new(),
+        (false, SinkKind::Discord) => args
+            .webhook
+            .iter()
+            .map(|url| {
+                let discord = Discord::new(
+                    url,
+                    args.bot_name.as_deref().unwrap_or(BOT_NAME),
+                    args.thread_id,
+                    args.send_attempts,
+                    client.clone(),
+                )?;
+                Ok(Box::new(
+                    discord
+                        .with_branding(args.avatar_url.as_deref(), args.thumbnail_url.as_deref()),
+                ) as _)
+            })
+            .collect::<Result<_>>()?,
+        (false, SinkKind::Slack) => args
+            .webhook
+            .iter()
+            .map(|url| Box::new(Slack::new(url, args.send_attempts, client.clone())) as _)
+            .collect(),
+        (false, SinkKind::Telegram) => args
+            .telegram_token
+            .as_deref()
+            .zip(args.telegram_chat.as_deref())
+            .map(|(token, chat)| {
+                Box::new(Telegram::new(
+                    token,
+                    chat,
+                    args.send_attempts,
+                    client.clone(),
+                )) as _
+            })
+            .into_iter()
+            .collect(),
+        (false, SinkKind::Generic) => {
+            let body = match &args.generic_template {
+                Some(path) => fs::read_to_string(path).with_context(|| {
+                    format!("Could not read body template {}", path.to_string_lossy())
+                })?,
+                None => generic::DEFAULT_BODY.to_string(),
+            };
+            args.generic_webhook
+                .iter()
+                .map(|url| {
+                    Ok(Box::new(Generic::new(
+                        url,
+                        &body,
+                        args.send_attempts,
+                        client.clone(),
+                    )?) as _)
+                })
+                .collect::<Result<_>>()?
+        }
+    };
+    let sink: Option<Box<dyn StatsSink>> = match sinks.len() {
+        0 | 1 => sinks.pop(),
+        _ => Some(Box::new(Broadcast::new(sinks))),
     };
-    println!("Looking for saves in: {}", location.to_string_lossy());
+
+    if args.location.is_empty() && args.simulate.is_none() {
+        let Some(location) = default_location() else {
+            return Err(anyhow!(
+                "Could not find the replay folder. Please supply a path to it with --location"
+            ));
+        };
+        args.location.push(location);
+    }
+
+    if args.simulate.is_none() {
+        // A missing folder may only be unmounted for now, the watcher picks it up once it is back.
+        // Only when none of them are there is it most likely a typo.
+        let missing: Vec<&PathBuf> = args
+            .location
+            .iter()
+            .filter(|location| !args.create_location && !location.exists())
+            .collect();
+        if !missing.is_empty() && missing.len() == args.location.len() {
+            check_location(missing[0], false)?;
+        }
+        for location in &args.location {
+            if missing.contains(&location) {
+                warn!(
+                    "Replay folder {} does not exist, watching it once it does",
+                    location.to_string_lossy()
+                );
+                continue;
+            }
+            check_location(location, args.create_location)?;
+            let replays = backfill_replays(
+                std::slice::from_ref(location),
+                Duration::MAX,
+                args.recursive,
+                args.replay_glob.as_ref(),
+            );
+            if replays.is_empty() {
+                warn!(
+                    "No replays in {} yet. New ones show up there when a match ends, if it is where Rocket League saves them",
+                    location.to_string_lossy()
+                );
+            } else {
+                info!(
+                    "Found {} replays in {}",
+                    replays.len(),
+                    location.to_string_lossy()
+                );
+            }
+        }
+    }
 
     let (tx, rx) = std::sync::mpsc::channel();
+    let shutdown_tx = tx.clone();
+    let simulate_tx = tx.clone();
+    let reset_tx = tx.clone();
+    let quit_tx = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = shutdown_tx.send(Message::Shutdown);
+    })?;
     // This example is a little bit misleading as you can just create one Config and use it for all watchers.
     // That way the pollwatcher specific stuff is still configured, if it should be used.
-    let mut watcher: Box<dyn Watcher> =
-        Box::new(RecommendedWatcher::new(tx, Config::default()).unwrap());
+    let mut watcher: Box<dyn Watcher> = Box::new(
+        RecommendedWatcher::new(
+            move |e| {
+                let _ = tx.send(Message::Fs(e));
+            },
+            Config::default(),
+        )
+        .unwrap(),
+    );
 
-    // watch some stuff
-    let Ok(_) = watcher
-        .watch(&location, RecursiveMode::NonRecursive) else {
-            return Err(anyhow!("Location was not valid and default location did not work. Please supply a path to the replay folder"));
-        };
+    // Every location feeds the same channel, so a broken one should not stop the others.
+    // A simulated session gets its replays from the folder it is given and watches nothing.
+    let mut locations = Locations::watch(
+        watcher.as_mut(),
+        if args.simulate.is_some() {
+            &[]
+        } else {
+            &args.location
+        },
+        args.recursive,
+        Instant::now(),
+    );
+    if locations.watching() == 0 && args.simulate.is_none() {
+        return Err(anyhow!("Location was not valid and default location did not work. Please supply a path to the replay folder"));
+    }
 
-    // Set up the running tally.
-    let mut tally: Tally = Tally {
-        player_stats: HashMap::new(),
-        games_played: 0,
+    // Set up the running tally, picking up where we left off if there is a state file.
+    let mut tally: Tally = match &args.state_file {
+        Some(path) if path.exists() => {
+            let tally = load_tally(path)?;
+            info!(
+                "Resuming session from {} ({} games played)",
+                path.to_string_lossy(),
+                tally.games_played
+            );
+            tally
+        }
+        _ => Tally::default(),
     };
+    tally.started_at.get_or_insert_with(Local::now);
 
-    if !args.no_discord {
-        let _res = client.send(|message| {
-            message.username(BOT_NAME).embed(|embed| {
-                embed
-                    .title("Starting new session")
-                    .description(indoc! {
-                        "The bot will try to single out the people that plays multiple times in the session, on either team.
-                        Please make sure to install Bakkesmod and make _Auto replay uploader_ do export to the filepath specified by you or the program.
-                        Stats are in the form: accumulated (last game)
-                    "})
-            })
-        }).await;
+    // The stats server and the TUI get a copy of the tally that is refreshed after every game.
+    // They anonymize what they show themselves, as `--players` and `--me` go by real names.
+    let live = (args.serve.is_some() || args.tui).then(|| Arc::new(Mutex::new(tally.clone())));
+    if let (Some(addr), Some(live)) = (&args.serve, &live) {
+        server::serve(addr, live.clone(), settings.clone(), move || {
+            let _ = reset_tx.send(Message::Reset);
+        })?;
     }
 
-    let mut current_file: Option<PathBuf> = None;
-    for e in rx {
-        match e {
-            Ok(event) => {
-                let Event {
-                    kind,
-                    paths,
-                    attrs: _,
-                } = event;
+    let mut db = match &args.db {
+        Some(path) => Some(Db::open(path)?),
+        None => None,
+    };
+    let mut csv_log = match &args.csv_out {
+        Some(path) => Some(CsvLog::open(path)?),
+        None => None,
+    };
+    if let (Some(db), true) = (&db, args.lifetime) {
+        print!("{}", render_lifetime(&db.lifetime()?));
+    }
 
-                if let Some(p) = paths.get(0) {
-                    let file_name = p.file_name().unwrap_or_default().to_string_lossy();
+    // The explanation goes out with the first stats, so starting early and not playing posts nothing.
+    let mut start_message = sink.is_some() && !args.no_start_message;
+    if let (Some(sink), true) = (&sink, start_message && args.eager_start_message) {
+        send_start_message(sink.as_ref(), &args).await;
+        start_message = false;
+    }
 
-                    // Bakkesmod opens the file (Create) then writes it (Modify).
-                    match kind {
-                        EventKind::Create(_e) => {
-                            println!("Replay created: {}", file_name);
-                            println!("Waiting for write");
-                            current_file = Some(p.clone());
-                            continue;
-                        }
-                        EventKind::Modify(_e) => {
-                            if let Some(c) = &current_file {
-                                if c == p {
-                                    println!("Replay written: {}", file_name);
-                                    println!("Sending stats");
-                                    current_file = None;
-                                } else {
-                                    continue;
-                                }
-                            } else {
-                                continue;
-                            }
-                        }
-                        _ => {
-                            continue;
+    if args.backfill {
+        // A replay is written after its match was played, so looking back to --since finds every game after it.
+        let mut window = *args.backfill_window;
+        if let Some(since) = args.since {
+            info!("Only backfilling games played after {}", since.to_rfc3339());
+            let age = (Local::now() - since).to_std().unwrap_or_default();
+            window = window.max(age);
+        }
+        let mut cache = args.replay_cache.as_deref().map(ReplayCache::load);
+        let reading = Instant::now();
+        let replays = backfill_replays(
+            &args.location,
+            window,
+            args.recursive,
+            args.replay_glob.as_ref(),
+        );
+        let mut cached = 0;
+        let mut games = Vec::new();
+        for p in &replays {
+            let read = match cache
+                .as_ref()
+                .and_then(|cache| cache.get(p, args.deep_parse))
+            {
+                Some(game) => {
+                    cached += 1;
+                    Ok(game)
+                }
+                None => parse_rl(p, args.deep_parse)
+                    .and_then(|replay| read_game(&replay))
+                    .inspect(|game| {
+                        if let Some(cache) = &mut cache {
+                            cache.insert(p, args.deep_parse, game.as_ref());
                         }
-                    }
-
-                    let extension = p.extension();
-                    if extension.is_none() || !extension.is_some_and(|os| os == "replay") {
+                    }),
+            };
+            match read {
+                Ok(Some(mut game)) => {
+                    game.played_at = game.played_at.or_else(|| file_date(p));
+                    if played_before(&game, args.since) || !settings.counts(&game) {
                         continue;
                     }
-                    let Ok(replay) = parse_rl(p) else {
-                        continue;
-                    };
-                    let Some(stats) = replay.properties.iter().find(|(s, _)| s == "PlayerStats") else {
-                        eprintln!("No playerstats for replay");
-                        continue;
-                    };
-                    let (_, stats) = stats;
-                    let HeaderProp::Array(stats) = stats else {
-                        continue;
-                    };
+                    games.push((p.clone(), game));
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Skipping replay {}: {}", p.to_string_lossy(), e),
+            }
+        }
+        info!(
+            "Read {} replays in {:.2}s, {} of them from the replay cache",
+            replays.len(),
+            reading.elapsed().as_secs_f64(),
+            cached
+        );
+        if let Some(Err(e)) = cache.as_mut().map(ReplayCache::save) {
+            error!("{:?}", e);
+        }
+        let added = add_earlier_games(
+            &mut games,
+            &mut tally,
+            &settings,
+            (db.as_mut(), csv_log.as_mut()),
+            args.ndjson,
+        );
+        write_tally(&args, &tally, live.as_deref());
+        info!(
+            "Backfilled {} games from the last {}",
+            added, args.backfill_window
+        );
+    }
 
-                    let team0_score = replay
-                        .properties
-                        .iter()
-                        .find(|(s, _)| s == "Team0Score")
-                        .map(|(_, v)| v.as_i32().unwrap_or_default())
-                        .unwrap_or_default();
-                    let team1_score = replay
-                        .properties
-                        .iter()
-                        .find(|(s, _)| s == "Team1Score")
-                        .map(|(_, v)| v.as_i32().unwrap_or_default())
-                        .unwrap_or_default();
-                    let team_win_lose = if team0_score == team1_score {
-                        (2, 2)
-                    } else if team0_score > team1_score {
-                        (0, 1)
-                    } else {
-                        (1, 0)
-                    };
+    for zip in &args.from_zip {
+        let mut games = Vec::new();
+        let replays = archive::read_replays(zip, |name, data| {
+            // Not a real path, but it says where the replay came from in the logs and the csv.
+            let p = zip.join(name);
+            match parse_replay(data, &p, args.deep_parse).and_then(|replay| read_game(&replay)) {
+                Ok(Some(game)) if settings.counts(&game) => games.push((p, game)),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping replay {}: {}", p.to_string_lossy(), e),
+            }
+        })?;
+        let added = add_earlier_games(
+            &mut games,
+            &mut tally,
+            &settings,
+            (db.as_mut(), csv_log.as_mut()),
+            args.ndjson,
+        );
+        write_tally(&args, &tally, live.as_deref());
+        info!(
+            "Added {} games from the {} replays in {}",
+            added,
+            replays,
+            zip.to_string_lossy()
+        );
+    }
 
-                    // Accumulate stats
-                    for player_stat in stats {
-                        let mut name: Option<String> = None;
-                        let mut score: usize = 0;
-                        let mut goals: usize = 0;
-                        let mut assists: usize = 0;
-                        let mut saves: usize = 0;
-                        let mut shots: usize = 0;
-                        let mut team: usize = 0;
-                        for (key, prop) in player_stat {
-                            match (key.as_str(), prop) {
-                                ("Name", HeaderProp::Str(v)) => name = Some(v.to_string()),
-                                ("Score", HeaderProp::Int(v)) => score = *v as usize,
-                                ("Goals", HeaderProp::Int(v)) => goals = *v as usize,
-                                ("Assists", HeaderProp::Int(v)) => assists = *v as usize,
-                                ("Saves", HeaderProp::Int(v)) => saves = *v as usize,
-                                ("Shots", HeaderProp::Int(v)) => shots = *v as usize,
-                                ("Team", HeaderProp::Int(v)) => team = *v as usize,
-                                _ => {}
-                            }
-                        }
+    if let Some(dir) = &args.simulate {
+        let replays = backfill_replays(
+            std::slice::from_ref(dir),
+            Duration::MAX,
+            false,
+            args.replay_glob.as_ref(),
+        );
+        if replays.is_empty() {
+            return Err(anyhow!(
+                "No replays to simulate in {}",
+                dir.to_string_lossy()
+            ));
+        }
+        info!(
+            "Simulating {} replays from {}, {} apart",
+            replays.len(),
+            dir.to_string_lossy(),
+            args.simulate_delay
+        );
+        simulate(replays, *args.simulate_delay, simulate_tx);
+    }
 
-                        let did_win = team == team_win_lose.0 as usize;
-                        let did_lose = team == team_win_lose.1;
-
-                        if let Some(name) = name {
-                            let stats = tally.player_stats.entry(name);
-                            stats
-                                .and_modify(|stats| {
-                                    stats.times_seen += 1;
-                                    stats.wins += did_win as usize;
-                                    stats.losses += did_lose as usize;
-                                    stats.score = (stats.score.0 + score, score);
-                                    stats.goals = (stats.goals.0 + goals, goals);
-                                    stats.assists = (stats.assists.0 + assists, assists);
-                                    stats.saves = (stats.saves.0 + saves, saves);
-                                    stats.shots = (stats.shots.0 + shots, shots);
-                                })
-                                .or_insert(PlayerStats {
-                                    times_seen: 1,
-                                    score: (score, score),
-                                    goals: (goals, goals),
-                                    assists: (assists, assists),
-                                    saves: (saves, saves),
-                                    shots: (shots, shots),
-                                    wins: did_win as usize,
-                                    losses: did_lose as usize,
-                                });
-                        }
+    // Bakkesmod opens the file (Create) then writes it (Modify), sometimes in several goes.
+    // A replay is only read once no event has arrived for it in SETTLE_TIME. Every replay is
+    // tracked on its own, so several being written at once (e.g. two game instances) all get read.
+    let mut pending = PendingReplays::new(*args.rewrite_cooldown);
+    // Games waiting for SEND_INTERVAL to pass since the last message, see below.
+    let mut unsent: Vec<Game> = Vec::new();
+    let mut last_sent: Option<Instant> = None;
+    // When the last game came in or the last heartbeat went out, see --heartbeat.
+    let mut quiet_since = Instant::now();
+    let tui = match (&live, args.tui) {
+        (Some(live), true) => Some(Tui::start(live.clone(), settings.clone(), move || {
+            let _ = quit_tx.send(Message::Shutdown);
+        })?),
+        _ => None,
+    };
+    loop {
+        let mut deadline = pending
+            .next_deadline()
+            .map_or(locations.next_deadline(), |settle| {
+                settle.min(locations.next_deadline())
+            });
+        if let (false, Some(sent)) = (unsent.is_empty(), last_sent) {
+            deadline = deadline.min(sent + SEND_INTERVAL);
+        }
+        if let Some(heartbeat) = args.heartbeat {
+            deadline = deadline.min(quiet_since + *heartbeat);
+        }
+        let message = rx.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        let mut written = Vec::new();
+        match message {
+            Ok(Message::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(Message::Fs(Ok(Event { kind, paths, .. }))) => {
+                for p in paths {
+                    if kind.is_remove() && locations.lose(watcher.as_mut(), &p, Instant::now()) {
+                        continue;
                     }
-                    tally.games_played += 1;
-
-                    // Write to discord.
-                    let mut stat_message =
-                        format!("## Game {games} finished\n\n", games = tally.games_played);
-                    let mut sorted: Vec<(&String, &PlayerStats)> =
-                        tally.player_stats.iter().collect();
-                    sorted.sort_unstable_by(|a, b| b.1.score.cmp(&a.1.score));
-                    for (name, stats) in sorted {
-                        if stats.times_seen != tally.games_played
-                            && stats.times_seen <= usize::max(3, tally.games_played / 2)
-                        {
-                            // This should sufficiently remove people not playing with you.
-                            continue;
+                    if !wanted_replay(&p, args.replay_glob.as_ref()) {
+                        continue;
+                    }
+                    match kind {
+                        EventKind::Create(_e) => {
+                            info!(
+                                "Replay created: {}, waiting for write",
+                                p.file_name().unwrap_or_default().to_string_lossy()
+                            );
+                            pending.created(p, Instant::now());
                         }
-                        let PlayerStats {
-                            times_seen,
-                            score,
-                            goals,
-                            assists,
-                            saves,
-                            shots,
-                            wins,
-                            losses,
-                        } = stats;
-                        let player_msg = formatdoc! {"
-                            ### {name}
-                            *Played {times_seen} games*
-                            - Wins/Losses: {wins}/{losses}
-                            - Score: {score_tally} ({score})
-                            - Goals: {goals_tally} ({goals})
-                            - Assists: {assists_tally} ({assists})
-                            - Saves: {saves_tally} ({saves})
-                            - Shots: {shots_tally} ({shots})
-                        ",
-                        name=name,
-                        times_seen=times_seen,
-                        wins=wins,
-                        losses=losses,
-                        score_tally=score.0,
-                        score=score.1,
-                        goals_tally=goals.0,
-                        goals=goals.1,
-                        assists_tally=assists.0,
-                        assists=assists.1,
-                        saves_tally=saves.0,
-                        saves=saves.1,
-                        shots_tally=shots.0,
-                        shots=shots.1
-                        };
-                        stat_message.push_str(&player_msg);
-                        // stat_message.push_str("\n");
+                        EventKind::Modify(_e) => pending.modified(&p, Instant::now()),
+                        EventKind::Remove(_e) if pending.removed(&p) => {
+                            info!(
+                                "Replay removed before it was written: {}",
+                                p.file_name().unwrap_or_default().to_string_lossy()
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Ok(Message::Fs(Err(e))) => {
+                error!("Watching for replays failed: {:?}", e);
+                // The error does not always say which folder it was about, so check them all.
+                let now = Instant::now();
+                for p in &e.paths {
+                    locations.lose(watcher.as_mut(), p, now);
+                }
+                locations.check(watcher.as_mut(), now);
+            }
+            Ok(Message::Replay(p)) => written.push(p),
+            Ok(Message::Reset) => {
+                info!(
+                    "Starting a new session, {} games were played in the last one",
+                    tally.games_played
+                );
+                tally.reset(Local::now());
+                unsent.clear();
+                write_tally(&args, &tally, live.as_deref());
+                let title = match &args.session_title {
+                    Some(title) => format!("New {}", title),
+                    None => "New session".to_string(),
+                };
+                let text = "The tally was reset, stats start from zero again.";
+                if let Some(sink) = &sink {
+                    if let Err(e) = sink.send_message(Some(&title), text).await {
+                        error!("Failed to send message to webhook: {}", e);
+                    }
+                } else if args.prints_stats() {
+                    println!("{}\n{}\n", title, text);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        locations.tick(watcher.as_mut(), Instant::now());
+        // Games waiting to go out are news enough, the heartbeat waits for them.
+        let heartbeat_due = args
+            .heartbeat
+            .is_some_and(|heartbeat| quiet_since.elapsed() >= *heartbeat);
+        if heartbeat_due && unsent.is_empty() {
+            quiet_since = Instant::now();
+            let text = render_heartbeat(&tally);
+            match &sink {
+                Some(sink) => {
+                    if let Err(e) = sink.send_message(None, &text).await {
+                        error!("Failed to send heartbeat to webhook: {}", e);
                     }
+                }
+                None => info!("{}", text),
+            }
+        }
+
+        written.extend(pending.take_settled(Instant::now()));
+        for p in &written {
+            info!(
+                "Replay written: {}",
+                p.file_name().unwrap_or_default().to_string_lossy()
+            );
 
-                    if !args.no_discord {
-                        let res = client
-                            .send(|message| {
-                                message
-                                    .username(BOT_NAME)
-                                    .embed(|embed| embed.description(&stat_message))
-                            })
-                            .await;
-                        if res.is_err() {
-                            eprintln!("Failed to send message to discord webhook");
-                            continue;
-                        };
-                        eprintln!("Sent stats to discord\n");
+            let replay = match read_written_replay(p, args.deep_parse) {
+                Ok(replay) => replay,
+                Err(ReadError::NotReady(e)) => {
+                    if pending.retry(p.clone(), Instant::now()) {
+                        info!("Replay is not ready yet, trying again: {}", e);
                     } else {
-                        print!("{}", stat_message);
+                        warn!("Skipping replay, it never finished writing: {}", e);
+                    }
+                    continue;
+                }
+                Err(ReadError::Unreadable(e)) => {
+                    warn!("Skipping replay, it is corrupt: {}", e);
+                    continue;
+                }
+            };
+            let mut game = match apply_replay(&mut tally, &replay, &settings) {
+                Ok(Some(game)) => game,
+                Ok(None) => {
+                    info!("Nothing to count (e.g. freeplay, workshop or a game counted already), skipping it");
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Skipping replay: {}", e);
+                    continue;
+                }
+            };
+            game.played_at = game.played_at.or_else(|| file_date(p));
+            quiet_since = Instant::now();
+            if let Some(party) = guess_party(&tally, &game, &settings) {
+                info!(
+                    "The game was {}, going by who keeps showing up",
+                    party.as_str()
+                );
+            }
+            write_tally(&args, &tally, live.as_deref());
+            record_game(db.as_mut(), csv_log.as_mut(), p, &game);
+            if args.ndjson {
+                println!("{}", events::game_event(p, &game, &tally, &settings));
+            }
+
+            if args.summary_only {
+                let due = args
+                    .summary_every
+                    .is_some_and(|every| tally.games_played.is_multiple_of(every));
+                if !due {
+                    continue;
+                }
+                let summary = render_summary(
+                    &tally,
+                    &settings,
+                    &args.session_title("so far"),
+                    Local::now(),
+                );
+                if let Some(sink) = &sink {
+                    if std::mem::take(&mut start_message) {
+                        send_start_message(sink.as_ref(), &args).await;
+                    }
+                    if let Err(e) = sink.send_report(&summary).await {
+                        error!("Failed to send session summary to webhook: {}", e);
+                        continue;
                     }
+                    info!("Sent session summary to {:?}", args.sink);
+                } else if args.prints_stats() {
+                    print!("{}", summary.to_terminal(color));
+                }
+                if args.chart {
+                    send_chart(&tally, &settings, sink.as_deref()).await;
                 }
+                continue;
+            }
+
+            unsent.push(game);
+        }
+
+        // Games that finish close together go out as one message, so bursts stay under rate limits.
+        let ready = sink.is_none() || last_sent.is_none_or(|sent| sent.elapsed() >= SEND_INTERVAL);
+        if unsent.is_empty() || !ready {
+            continue;
+        }
+        let stat_message = match unsent.as_slice() {
+            [game] => render_game(&tally, game, &settings),
+            games => render_games(&tally, games, &settings),
+        };
+        unsent.clear();
+        if let Some(sink) = &sink {
+            if std::mem::take(&mut start_message) {
+                send_start_message(sink.as_ref(), &args).await;
             }
-            Err(e) => {
-                eprintln!("{:?}", e);
+            last_sent = Some(Instant::now());
+            if let Err(e) = sink.send_report(&stat_message).await {
+                error!("Failed to send message to webhook: {}", e);
+                continue;
+            };
+            info!("Sent stats to {:?}", args.sink);
+        } else if args.prints_stats() {
+            print!("{}", stat_message.to_terminal(color));
+        }
+    }
+
+    if let Some(Err(e)) = tui.map(Tui::stop) {
+        error!("{:?}", e);
+    }
+    // Games still waiting out SEND_INTERVAL go out now, before the summary.
+    if !unsent.is_empty() {
+        let stat_message = match unsent.as_slice() {
+            [game] => render_game(&tally, game, &settings),
+            games => render_games(&tally, games, &settings),
+        };
+        if let Some(sink) = &sink {
+            if std::mem::take(&mut start_message) {
+                send_start_message(sink.as_ref(), &args).await;
+            }
+            match sink.send_report(&stat_message).await {
+                Ok(()) => info!("Sent stats to {:?}", args.sink),
+                Err(e) => error!("Failed to send message to webhook: {}", e),
             }
+        } else if args.prints_stats() {
+            print!("{}", stat_message.to_terminal(color));
+        }
+    }
+    if args.print_pseudonyms {
+        eprint!("{}", render_pseudonyms(&tally));
+    }
+    if let Some(path) = &args.exit_summary {
+        let summary = events::exit_summary(&tally, &settings, Local::now());
+        match write_atomic(path, &summary) {
+            Ok(()) => info!(
+                "Wrote the session's key numbers to {}",
+                path.to_string_lossy()
+            ),
+            Err(e) => error!("Failed to write exit summary: {:?}", e),
+        }
+    }
+    if tally.games_played == 0 {
+        info!("No games played, skipping the session summary");
+        if args.exit_summary.is_some() {
+            return Err(anyhow!("No games were played"));
+        }
+        return Ok(());
+    }
+    let summary = render_summary(
+        &tally,
+        &settings,
+        &args.session_title("complete"),
+        Local::now(),
+    );
+    if let Some(sink) = &sink {
+        if start_message {
+            send_start_message(sink.as_ref(), &args).await;
+        }
+        let res = sink.send_report(&summary).await;
+        if let Err(e) = res {
+            error!("Failed to send session summary to webhook: {}", e);
+        } else {
+            info!("Sent session summary to {:?}", args.sink);
         }
+    } else if !args.ndjson {
+        print!("{}", summary.to_terminal(color));
+    }
+    if args.chart {
+        send_chart(&tally, &settings, sink.as_deref()).await;
     }
 
     Ok(())
 }
 
-fn parse_rl(filename: &PathBuf) -> Result<Replay> {
+/// Posts what the stats mean, at the start of a session. Failing is only logged.
+async fn send_start_message(sink: &dyn StatsSink, args: &Args) {
+    let res = sink
+        .send_message(
+            Some(&match &args.session_title {
+                Some(title) => format!("Starting {}", title),
+                None => "Starting new session".to_string(),
+            }),
+            indoc! {
+                "The bot will try to single out the people that plays multiple times in the session, on either team.
+                Please make sure to install Bakkesmod and make _Auto replay uploader_ do export to the filepath specified by you or the program.
+                Stats are in the form: accumulated (last game)
+            "},
+        )
+        .await;
+    if let Err(e) = res {
+        error!("Failed to send message to webhook: {}", e);
+    }
+}
+
+/// Posts a bar chart of the tracked players' goals, or writes it to `CHART_FILE` without a sink.
+/// A chart is nice to have, so failing is only logged.
+async fn send_chart(tally: &Tally, settings: &Settings, sink: Option<&dyn StatsSink>) {
+    let goals: Vec<(String, usize)> = ranked_players(tally, settings)
+        .into_iter()
+        .map(|(_, stats)| (stats.shown_name(settings), stats.goals.0))
+        .collect();
+    let png = match chart::bar_chart("Goals this session", &goals) {
+        Ok(Some(png)) => png,
+        Ok(None) => return,
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    let res = match sink {
+        Some(sink) => sink.send_image("Goals this session", &png).await,
+        None => fs::write(CHART_FILE, png)
+            .map(|_| info!("Wrote the goals chart to {}", CHART_FILE))
+            .map_err(|e| anyhow!(e)),
+    };
+    if let Err(e) = res {
+        error!("Failed to send goals chart: {}", e);
+    }
+}
+
+/// Adds games that were played before we got to them, from --backfill or --from-zip, in the
+/// order they were played. Returns how many were not counted already.
+fn add_earlier_games(
+    games: &mut [(PathBuf, Game)],
+    tally: &mut Tally,
+    settings: &Settings,
+    (mut db, mut csv_log): (Option<&mut Db>, Option<&mut CsvLog>),
+    ndjson: bool,
+) -> usize {
+    // Files can be copied around, the match date is the better order when the replay has one.
+    games.sort_by_key(|(_, game)| game.played_at);
+    let mut added = 0;
+    for (p, game) in games.iter() {
+        if !tally.first_seen(game) {
+            debug!("Already counted {}, skipping it", p.to_string_lossy());
+            continue;
+        }
+        added += 1;
+        // The session started with the games it is catching up on, not when we did.
+        if let (Some(started_at), Some(played_at)) = (&mut tally.started_at, game.played_at) {
+            *started_at = (*started_at).min(played_at);
+        }
+        tally.add_game(game, settings);
+        record_game(db.as_deref_mut(), csv_log.as_deref_mut(), p, game);
+        if ndjson {
+            println!("{}", events::game_event(p, game, tally, settings));
+        }
+    }
+    added
+}
+
+/// Reads the game from a replay and adds it to the tally. Replays that are not a match are
+/// left out, see [`read_game`], and so are games the settings leave out, see [`Settings::counts`].
+fn apply_replay(tally: &mut Tally, replay: &Replay, settings: &Settings) -> Result<Option<Game>> {
+    let Some(game) = read_game(replay)?.filter(|game| settings.counts(game)) else {
+        return Ok(None);
+    };
+    if !tally.first_seen(&game) {
+        debug!("Game was counted already, the replay is probably a copy");
+        return Ok(None);
+    }
+    tally.add_game(&game, settings);
+    Ok(Some(game))
+}
+
+/// What `--heartbeat` posts when no game came in for a while.
+fn render_heartbeat(tally: &Tally) -> String {
+    match tally.games_played {
+        0 => "Still tracking, no games yet".to_string(),
+        1 => "Still tracking, 1 game so far".to_string(),
+        games => format!("Still tracking, {games} games so far"),
+    }
+}
+
+/// The name of a rank tier, see [`deep::skill_tiers`]. Nothing for unranked.
+fn rank_name(tier: u8) -> Option<String> {
+    const RANKS: [&str; 7] = [
+        "Bronze",
+        "Silver",
+        "Gold",
+        "Platinum",
+        "Diamond",
+        "Champion",
+        "Grand Champion",
+    ];
+    const DIVISIONS: [&str; 3] = ["I", "II", "III"];
+    match tier {
+        1..=21 => {
+            let tier = usize::from(tier - 1);
+            Some(format!("{} {}", RANKS[tier / 3], DIVISIONS[tier % 3]))
+        }
+        22 => Some("Supersonic Legend".to_string()),
+        _ => None,
+    }
+}
+
+/// A line for every player whose rank changed with `games`, e.g. "alice promoted to Diamond
+/// II!", followed by an empty line. Nothing when no rank changed, or the replays had no ranks.
+fn render_rank_changes(tally: &Tally, games: &[Game], settings: &Settings) -> String {
+    let mut announced = BTreeSet::new();
+    let mut lines = String::new();
+    for game in games {
+        for player in game.players.iter().filter(|p| p.rank.is_some()) {
+            let (id, _) = settings.identity(player);
+            if settings.excludes(player) || !announced.insert((id, &game.playlist)) {
+                continue;
+            }
+            let Some(&(now, before)) = tally
+                .player_stats
+                .get(id)
+                .and_then(|stats| stats.ranks.get(&game.playlist))
+            else {
+                continue;
+            };
+            let Some(rank) = rank_name(now) else {
+                continue;
+            };
+            let name = escape_markdown(&settings.player_name(tally, player));
+            if now > before {
+                lines.push_str(&format!("{name} promoted to {rank}!\n"));
+            } else if now < before {
+                lines.push_str(&format!("{name} demoted to {rank}\n"));
+            }
+        }
+    }
+    if !lines.is_empty() {
+        lines.push('\n');
+    }
+    lines
+}
+
+/// The message for a game that was just added to the tally, with the running tally of its
+/// playlist when there is one per playlist.
+fn render_game(tally: &Tally, game: &Game, settings: &Settings) -> Report {
+    let shown = match tally.playlists.get(&game.playlist) {
+        Some(playlist) if settings.per_playlist => playlist,
+        _ => tally,
+    };
+    let number = if settings.plays_in(game) {
+        format!("Game {}", shown.games_played)
+    } else {
+        "Spectated game".to_string()
+    };
+    let mut message = format!(
+        "## {number} finished ({playlist}{tags})\n",
+        playlist = game.playlist,
+        tags = settings
+            .game_tags(tally, game)
+            .map(|tags| format!(", {tags}"))
+            .unwrap_or_default()
+    );
+    message.push_str(&format!("{}\n", settings.score(game)));
+    if let Some(played_at) = game.played_at {
+        message.push_str(&format!(
+            "*Played {}*\n",
+            played_at.format("%Y-%m-%d %H:%M")
+        ));
+    }
+    message.push('\n');
+    if let Some(mvp) = game
+        .players
+        .iter()
+        .find(|player| player.mvp && !settings.excludes(player))
+    {
+        let guessed = if game.mvp_guessed {
+            " (top scorer of the winning team)"
+        } else {
+            ""
+        };
+        message.push_str(&format!(
+            "MVP: {}{}\n\n",
+            escape_markdown(&settings.player_name(tally, mvp)),
+            guessed
+        ));
+    }
+    message.push_str(&render_rank_changes(
+        tally,
+        std::slice::from_ref(game),
+        settings,
+    ));
+    Report {
+        title: None,
+        header: message,
+        players: ranked_players(shown, settings)
+            .into_iter()
+            .map(|(_, stats)| render_player(stats, settings))
+            .collect(),
+        footer: None,
+        win_rate: win_rate(shown, settings),
+    }
+}
+
+/// One message for several games that finished close together, with the running tally after
+/// the last of them. Per playlist only if they were all in the same playlist.
+fn render_games(tally: &Tally, games: &[Game], settings: &Settings) -> Report {
+    let playlist = games
+        .first()
+        .map(|game| &game.playlist)
+        .filter(|playlist| games.iter().all(|game| game.playlist == **playlist));
+    let shown = match playlist.and_then(|playlist| tally.playlists.get(playlist)) {
+        Some(playlist) if settings.per_playlist => playlist,
+        _ => tally,
+    };
+    let mut message = format!("## {} games finished\n", games.len());
+    for game in games {
+        message.push_str(&format!(
+            "- {playlist}: {score}{tags}",
+            playlist = game.playlist,
+            score = settings.score(game),
+            tags = settings
+                .game_tags(tally, game)
+                .map(|tags| format!(" ({tags})"))
+                .unwrap_or_default()
+        ));
+        if let Some(mvp) = game
+            .players
+            .iter()
+            .find(|player| player.mvp && !settings.excludes(player))
+        {
+            message.push_str(&format!(
+                ", MVP: {}",
+                escape_markdown(&settings.player_name(tally, mvp))
+            ));
+        }
+        message.push('\n');
+    }
+    message.push('\n');
+    message.push_str(&render_rank_changes(tally, games, settings));
+    Report {
+        title: None,
+        header: message,
+        players: ranked_players(shown, settings)
+            .into_iter()
+            .map(|(_, stats)| render_player(stats, settings))
+            .collect(),
+        footer: None,
+        win_rate: win_rate(shown, settings),
+    }
+}
+
+/// The whole session so far: games played, win rate and every tracked player.
+fn render_summary(tally: &Tally, settings: &Settings, title: &str, now: DateTime<Local>) -> Report {
+    let win_rate = win_rate(tally, settings);
+    let mut players = ranked_players(tally, settings);
+    // Only the top of the list is shown with --max-players, the rest is just counted.
+    let others = players
+        .len()
+        .saturating_sub(settings.max_players.unwrap_or(usize::MAX));
+    players.truncate(players.len() - others);
+    if settings.group_by_team {
+        // Stable, so players keep their order within their team.
+        players.sort_by_key(|(_, stats)| stats.team.unwrap_or(usize::MAX));
+    }
+    Report {
+        title: Some(title.to_string()),
+        header: format!(
+            "## {games} games played\n{pace}{totals}{provisional}{fact}{teams}\n",
+            games = tally.games_played,
+            pace = render_pace(tally, now),
+            totals = render_team_totals(tally, &settings.presence, win_rate),
+            provisional = render_provisional(tally, settings),
+            fact = render_fun_fact(tally, settings),
+            teams = if settings.group_by_team {
+                "*Players are listed under the team of their latest game*\n"
+            } else {
+                ""
+            },
+        ),
+        players: players
+            .iter()
+            .map(|(id, stats)| {
+                let mut player = render_player(stats, settings);
+                if let Some(baseline) = &settings.baseline {
+                    let baseline = baseline.player_stats.get(*id);
+                    player.stats.push_str(&render_baseline(stats, baseline));
+                }
+                if settings.group_by_team {
+                    player.group = Some(team_group(&players, stats.team, settings));
+                }
+                player
+            })
+            .collect(),
+        footer: match others {
+            0 => None,
+            1 => Some("...and 1 other".to_string()),
+            others => Some(format!("...and {others} others")),
+        },
+        win_rate,
+    }
+}
+
+/// The team listed players are under with `--group-by-team`, with the totals of `players` in it.
+fn team_group(players: &[(&str, &PlayerStats)], team: Option<usize>, settings: &Settings) -> Group {
+    let members: Vec<&PlayerStats> = players
+        .iter()
+        .map(|(_, stats)| *stats)
+        .filter(|stats| stats.team == team)
+        .collect();
+    let total = |stat: fn(&PlayerStats) -> usize| members.iter().map(|s| stat(s)).sum::<usize>();
+    Group {
+        name: match team {
+            Some(0) => settings.team_names.0.clone(),
+            Some(1) => settings.team_names.1.clone(),
+            _ => "No team".to_string(),
+        },
+        subtotal: format!(
+            "*Goals {goals}, saves {saves}, score {score}*",
+            goals = total(|stats| stats.goals.0),
+            saves = total(|stats| stats.saves.0),
+            score = total(|stats| stats.score.0),
+        ),
+    }
+}
+
+/// How the player does this session compared to `baseline`, their stats from an earlier one.
+/// Averages are per game, so sessions of any length compare.
+fn render_baseline(stats: &PlayerStats, baseline: Option<&PlayerStats>) -> String {
+    let Some(baseline) = baseline.filter(|baseline| baseline.times_seen > 0) else {
+        return "*New since the baseline*\n".to_string();
+    };
+    let average = |stats: &PlayerStats, total: usize| total as f64 / stats.times_seen.max(1) as f64;
+    let score = average(stats, stats.score.0);
+    let goals = average(stats, stats.goals.0);
+    let saves = average(stats, stats.saves.0);
+    let mut text = formatdoc! {"
+        - Score avg: {score:.0} ({score_delta:+.0} vs baseline)
+        - Goals avg: {goals:.1} ({goals_delta:+.1} vs baseline)
+        - Saves avg: {saves:.1} ({saves_delta:+.1} vs baseline)
+    ",
+    score_delta = score - average(baseline, baseline.score.0),
+    goals_delta = goals - average(baseline, baseline.goals.0),
+    saves_delta = saves - average(baseline, baseline.saves.0),
+    };
+    if let (Some(rate), Some(baseline_rate)) = (stats.win_rate(), baseline.win_rate()) {
+        let delta = rate as i64 - baseline_rate as i64;
+        text.push_str(&format!("- Win rate: {rate}% ({delta:+} vs baseline)\n"));
+    }
+    text
+}
+
+/// A fact about the players that are part of the session, or nothing if none stands out.
+type FunFact = fn(&[(&str, &PlayerStats)], &Settings) -> Option<String>;
+
+/// The facts the summary picks from, see [`render_fun_fact`].
+const FUN_FACTS: &[FunFact] = &[most_saves, best_accuracy, most_improved, most_mvps];
+
+/// Players need this many shots before their accuracy is worth pointing out.
+const MIN_SHOTS_FOR_ACCURACY: usize = 3;
+/// Form follows the first games closely, so it only shows improvement after this many.
+const MIN_GAMES_FOR_IMPROVEMENT: usize = 5;
+
+/// Something that stands out in the session, for the summary. The fact goes round with the
+/// games played, skipping ones that do not apply, e.g. when there is a tie.
+fn render_fun_fact(tally: &Tally, settings: &Settings) -> String {
+    let players = tracked_players(tally, &settings.presence);
+    // Standing out takes someone to compare with.
+    if players.len() < 2 {
+        return String::new();
+    }
+    (0..FUN_FACTS.len())
+        .find_map(|i| FUN_FACTS[(tally.games_played + i) % FUN_FACTS.len()](&players, settings))
+        .map(|fact| format!("*Fun fact: {fact}*\n"))
+        .unwrap_or_default()
+}
+
+/// The player with the most of `value`, if they have any and nobody else has as much.
+fn standout<'a>(
+    players: &[(&str, &'a PlayerStats)],
+    value: impl Fn(&PlayerStats) -> Option<f64>,
+) -> Option<(&'a PlayerStats, f64)> {
+    let mut values: Vec<(&PlayerStats, f64)> = players
+        .iter()
+        .filter_map(|(_, stats)| Some((*stats, value(stats)?)))
+        .filter(|(_, value)| *value > 0.0)
+        .collect();
+    values.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+    match values.as_slice() {
+        [first, second, ..] if first.1 == second.1 => None,
+        [first, ..] => Some(*first),
+        [] => None,
+    }
+}
+
+fn most_saves(players: &[(&str, &PlayerStats)], settings: &Settings) -> Option<String> {
+    let (stats, saves) = standout(players, |stats| Some(stats.saves.0 as f64))?;
+    let name = escape_markdown(&stats.shown_name(settings));
+    Some(format!("{name} has made the most saves tonight ({saves})"))
+}
+
+fn best_accuracy(players: &[(&str, &PlayerStats)], settings: &Settings) -> Option<String> {
+    let (stats, accuracy) = standout(players, |stats| {
+        (stats.shots.0 >= MIN_SHOTS_FOR_ACCURACY).then(|| stats.shot_accuracy() as f64)
+    })?;
+    let name = escape_markdown(&stats.shown_name(settings));
+    Some(format!(
+        "{name} has the best shot accuracy tonight ({accuracy}%)"
+    ))
+}
+
+/// Whose form is furthest above their score per game, so who has been getting better.
+fn most_improved(players: &[(&str, &PlayerStats)], settings: &Settings) -> Option<String> {
+    let (stats, _) = standout(players, |stats| {
+        (stats.times_seen >= MIN_GAMES_FOR_IMPROVEMENT)
+            .then_some(stats.form? - stats.per_game(stats.score.0) as f64)
+    })?;
+    let name = escape_markdown(&stats.shown_name(settings));
+    Some(format!(
+        "Most improved: {name}, in form at {:.0} against {} per game",
+        stats.form.unwrap_or_default(),
+        stats.per_game(stats.score.0)
+    ))
+}
+
+fn most_mvps(players: &[(&str, &PlayerStats)], settings: &Settings) -> Option<String> {
+    let (stats, _) = standout(players, |stats| Some(stats.mvps as f64))?;
+    let name = escape_markdown(&stats.shown_name(settings));
+    let times = match stats.mvps {
+        1 => "once".to_string(),
+        mvps => format!("{mvps} times"),
+    };
+    Some(format!("{name} has been MVP the most tonight ({times})"))
+}
+
+/// Everyone's lifetime stats from `--db`, one line each with the numbers lined up. Names are
+/// padded by how wide they show, so emoji and CJK names line up too.
+fn render_lifetime(players: &[Lifetime]) -> String {
+    let width = players
+        .iter()
+        .map(|player| player.name.width())
+        .max()
+        .unwrap_or_default();
+    let mut text = "Lifetime stats:\n".to_string();
+    for player in players {
+        text.push_str(&format!(
+            "  {name}:{pad} {} games, {} wins, {} losses, {} goals\n",
+            player.games,
+            player.wins,
+            player.losses,
+            player.goals,
+            name = player.name,
+            pad = " ".repeat(width - player.name.width()),
+        ));
+    }
+    text
+}
+
+/// Who is behind every pseudonym, for `--print-pseudonyms`.
+fn render_pseudonyms(tally: &Tally) -> String {
+    let mut players: Vec<&PlayerStats> = tally.player_stats.values().collect();
+    players.sort_unstable_by_key(|stats| stats.first_seen);
+    players
+        .iter()
+        .map(|stats| format!("{}: {}\n", stats.pseudonym(), stats.name))
+        .collect()
+}
+
+/// How long the session has been going and how many games that made per hour, if we know when
+/// it started. The rate is left out until it has gone on for a minute, as it means nothing before.
+fn render_pace(tally: &Tally, now: DateTime<Local>) -> String {
+    let Some(started_at) = tally.started_at else {
+        return String::new();
+    };
+    let elapsed = (now - started_at).num_seconds().max(0);
+    let mut pace = format!("*Playing for {}h {:02}m", elapsed / 3600, elapsed / 60 % 60);
+    if elapsed >= 60 {
+        let per_hour = tally.games_played as f64 * 3600.0 / elapsed as f64;
+        pace.push_str(&format!(", {per_hour:.1} games per hour"));
+    }
+    pace.push_str("*\n");
+    pace
+}
+
+/// Players who are not shown yet, with how many more games they need to be. Players seen only
+/// once are left out, as those are mostly opponents.
+fn render_provisional(tally: &Tally, settings: &Settings) -> String {
+    let presence = &settings.presence;
+    let mut provisional: Vec<(usize, String)> = tally
+        .player_stats
+        .iter()
+        .filter(|(id, stats)| {
+            stats.times_seen > 1 && !presence.is_present(id, stats, tally.games_played)
+        })
+        .filter_map(|(id, stats)| {
+            let needed = presence.games_needed(id, stats, tally.games_played)?;
+            Some((needed, escape_markdown(&stats.shown_name(settings))))
+        })
+        .collect();
+    if provisional.is_empty() {
+        return String::new();
+    }
+    provisional.sort_unstable();
+    let players: Vec<String> = provisional
+        .iter()
+        .map(|(needed, name)| {
+            let games = if *needed == 1 { "game" } else { "games" };
+            format!("{name} ({needed} more {games})")
+        })
+        .collect();
+    format!("*Almost there: {}*\n", players.join(", "))
+}
+
+/// Goals and saves of the tracked players together, their win rate and how long games took.
+/// Every player only counts their own goals and saves, so adding them up counts nothing twice.
+fn render_team_totals(tally: &Tally, presence: &Presence, win_rate: usize) -> String {
+    let players = tracked_players(tally, presence);
+    let goals: usize = players.iter().map(|(_, stats)| stats.goals.0).sum();
+    let saves: usize = players.iter().map(|(_, stats)| stats.saves.0).sum();
+    let mut totals =
+        format!("### Team totals\n- Goals: {goals}\n- Saves: {saves}\n- Win rate: {win_rate}%\n");
+    if let Some(duration) = tally.average_duration() {
+        let seconds = duration.round() as u64;
+        totals.push_str(&format!(
+            "- Average game length: {}:{:02}\n",
+            seconds / 60,
+            seconds % 60
+        ));
+    }
+    totals
+}
+
+/// Players that have been seen often enough to be part of the session, with their key in the
+/// tally, in no particular order.
+fn tracked_players<'a>(tally: &'a Tally, presence: &Presence) -> Vec<(&'a str, &'a PlayerStats)> {
+    // This should sufficiently remove people not playing with you.
+    tally
+        .player_stats
+        .iter()
+        .filter(|(id, stats)| presence.is_present(id, stats, tally.games_played))
+        .map(|(id, stats)| (id.as_str(), stats))
+        .collect()
+}
+
+/// The tracked players in the order they are listed, see `--sort-by`.
+fn ranked_players<'a>(tally: &'a Tally, settings: &Settings) -> Vec<(&'a str, &'a PlayerStats)> {
+    let mut sorted = tracked_players(tally, &settings.presence);
+    sorted.sort_unstable_by(|(_, a), (_, b)| settings.sort_by.compare(a, b, settings.anonymize));
+    sorted
+}
+
+/// Win rate of the tracked players in percent, counting only games with a result.
+/// For a group that plays every game together this is the win rate of the group. With `--me`
+/// it is only that player's.
+fn win_rate(tally: &Tally, settings: &Settings) -> usize {
+    let players = match settings.me {
+        Some(_) => tally
+            .player_stats
+            .iter()
+            .filter(|(id, stats)| settings.is_me(id, &stats.name))
+            .map(|(id, stats)| (id.as_str(), stats))
+            .collect(),
+        None => tracked_players(tally, &settings.presence),
+    };
+    let (wins, decided) = players.iter().fold((0, 0), |(wins, decided), (_, stats)| {
+        (wins + stats.wins, decided + stats.wins + stats.losses)
+    });
+    (wins * 100).checked_div(decided).unwrap_or_default()
+}
+
+/// The placeholders a `--template` can use, see [`Column::line`].
+const PLAYER_PLACEHOLDERS: &[&str] = &[
+    "name",
+    "times_seen",
+    "wins",
+    "losses",
+    "draws",
+    "mvps",
+    "score_tally",
+    "score",
+    "score_avg",
+    "score_best",
+    "form",
+    "rating",
+    "goals_tally",
+    "goals",
+    "goals_avg",
+    "goals_best",
+    "assists_tally",
+    "assists",
+    "assists_best",
+    "participation_tally",
+    "participation",
+    "saves_tally",
+    "saves",
+    "saves_avg",
+    "saves_best",
+    "shots_tally",
+    "shots",
+    "accuracy",
+    "differential_tally",
+    "differential",
+];
+
+/// The lines of a player's stats, see `--columns`. Unless `--template` says otherwise, players
+/// are shown with all of them, in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Column {
+    Games,
+    /// Wins, losses and draws.
+    Wins,
+    Mvps,
+    Score,
+    Goals,
+    Assists,
+    /// Goals and assists together.
+    Participation,
+    Saves,
+    Shots,
+    Accuracy,
+    Differential,
+    Rating,
+}
+
+impl Column {
+    /// The line of the player template showing the column.
+    fn line(self) -> &'static str {
+        match self {
+            Column::Games => "*Played {times_seen} games*",
+            Column::Wins => "- Wins/Losses/Draws: {wins}/{losses}/{draws}",
+            Column::Mvps => "- MVPs: {mvps}",
+            Column::Score => {
+                "- Score: {score_tally} ({score}), {score_avg} per game, form {form} (best: {score_best})"
+            }
+            Column::Goals => {
+                "- Goals: {goals_tally} ({goals}), {goals_avg} per game (best: {goals_best})"
+            }
+            Column::Assists => "- Assists: {assists_tally} ({assists}) (best: {assists_best})",
+            Column::Participation => "- G+A: {participation_tally} ({participation})",
+            Column::Saves => {
+                "- Saves: {saves_tally} ({saves}), {saves_avg} per game (best: {saves_best})"
+            }
+            Column::Shots => "- Shots: {shots_tally} ({shots})",
+            Column::Accuracy => "- Shot accuracy: {accuracy}%",
+            Column::Differential => "- Goal differential: {differential_tally} ({differential})",
+            Column::Rating => "- Rating: {rating}",
+        }
+    }
+}
+
+/// The player template showing `columns` in that order, or all of them when there are none.
+fn columns_template(columns: &[Column]) -> String {
+    let columns = match columns {
+        [] => Column::value_variants(),
+        columns => columns,
+    };
+    columns
+        .iter()
+        .map(|column| format!("{}\n", column.line()))
+        .collect()
+}
+
+/// A player's rating and how much the last game moved it in every playlist they played, e.g.
+/// "1016 (+16) in Online 2v2".
+fn render_ratings(ratings: &BTreeMap<String, Rating>) -> String {
+    if ratings.is_empty() {
+        return "-".to_string();
+    }
+    ratings
+        .iter()
+        .map(|(playlist, rating)| {
+            format!("{:.0} ({:+.0}) in {playlist}", rating.rating, rating.delta)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The heading and stats of a player.
+fn render_player(stats: &PlayerStats, settings: &Settings) -> PlayerReport {
+    let PlayerStats {
+        name: _,
+        recent: _,
+        streak: _,
+        form,
+        ranks: _,
+        team: _,
+        ratings,
+        first_seen: _,
+        times_seen,
+        score,
+        goals,
+        assists,
+        saves,
+        shots,
+        goal_differential,
+        best,
+        wins,
+        losses,
+        draws,
+        mvps,
+        deep,
+    } = stats;
+    let name = escape_markdown(&stats.shown_name(settings));
+    let heading = format!(
+        "{name}{trend}{streak}",
+        trend = stats
+            .trend(settings.trend_window)
+            .map(|arrow| format!(" {arrow}"))
+            .unwrap_or_default(),
+        streak = stats
+            .streak()
+            .map(|streak| format!(" {streak}"))
+            .unwrap_or_default()
+    );
+    let values = HashMap::from([
+        ("name", name),
+        ("times_seen", times_seen.to_string()),
+        ("wins", wins.to_string()),
+        ("losses", losses.to_string()),
+        ("draws", draws.to_string()),
+        ("mvps", mvps.to_string()),
+        ("score_tally", score.0.to_string()),
+        ("score", score.1.to_string()),
+        ("score_avg", stats.per_game(score.0).to_string()),
+        ("score_best", best.score.to_string()),
+        (
+            "form",
+            form.map_or_else(
+                || stats.per_game(score.0).to_string(),
+                |form| format!("{form:.0}"),
+            ),
+        ),
+        ("rating", render_ratings(ratings)),
+        ("goals_tally", goals.0.to_string()),
+        ("goals", goals.1.to_string()),
+        ("goals_avg", stats.per_game(goals.0).to_string()),
+        ("goals_best", best.goals.to_string()),
+        ("assists_tally", assists.0.to_string()),
+        ("assists", assists.1.to_string()),
+        ("assists_best", best.assists.to_string()),
+        ("participation_tally", (goals.0 + assists.0).to_string()),
+        ("participation", (goals.1 + assists.1).to_string()),
+        ("saves_tally", saves.0.to_string()),
+        ("saves", saves.1.to_string()),
+        ("saves_avg", stats.per_game(saves.0).to_string()),
+        ("saves_best", best.saves.to_string()),
+        ("shots_tally", shots.0.to_string()),
+        ("shots", shots.1.to_string()),
+        ("accuracy", stats.shot_accuracy().to_string()),
+        ("differential_tally", format!("{:+}", goal_differential.0)),
+        ("differential", format!("{:+}", goal_differential.1)),
+    ]);
+    let mut rendered = settings.template.render(&values);
+    if let Some((total, last)) = deep {
+        rendered.push_str(&formatdoc! {"
+            - Demos: {inflicted_tally} given / {taken_tally} taken ({inflicted}/{taken})
+            - Boost collected/used: {collected_tally}/{used_tally} ({collected}/{used})
+            - Touches: {touches_tally} ({touches})
+            - Own goals: {own_goals_tally} ({own_goals})
+        ",
+        inflicted_tally=total.demos_inflicted,
+        taken_tally=total.demos_taken,
+        inflicted=last.demos_inflicted,
+        taken=last.demos_taken,
+        collected_tally=total.boost_collected,
+        used_tally=total.boost_used,
+        collected=last.boost_collected,
+        used=last.boost_used,
+        touches_tally=total.touches,
+        touches=last.touches,
+        own_goals_tally=total.own_goals,
+        own_goals=last.own_goals
+        });
+        // Score rather than goals, so defenders who spend their boost well show too.
+        if let Some(efficiency) = (score.0 * 100).checked_div(total.boost_used) {
+            rendered.push_str(&format!("- Score per 100 boost used: {efficiency}\n"));
+        }
+    } else if settings.deep_parse {
+        rendered.push_str(
+            "- Demos, boost and touches: not available, the network data could not be read\n",
+        );
+    }
+    PlayerReport {
+        heading,
+        stats: rendered,
+        win_rate: stats.win_rate(),
+        group: None,
+    }
+}
+
+/// Everything read from a replay, for `validate`. Plain text, as it is meant for bug reports.
+fn render_validation(game: &Game, team_names: (&str, &str)) -> String {
+    let mut text = format!(
+        "Playlist: {}\nScore: {} {}-{} {}{}\n",
+        game.playlist,
+        team_names.0,
+        game.team_scores.0,
+        game.team_scores.1,
+        team_names.1,
+        if game.forfeit { " (forfeit)" } else { "" }
+    );
+    if let Some(played_at) = game.played_at {
+        text.push_str(&format!("Played: {}\n", played_at.to_rfc3339()));
+    }
+    if let Some(duration) = game.duration {
+        text.push_str(&format!("Length: {duration:.1}s\n"));
+    }
+    if let Some(ranked) = game.ranked {
+        text.push_str(&format!("Ranked: {}\n", if ranked { "yes" } else { "no" }));
+    }
+    if let Some(id) = &game.replay_id {
+        text.push_str(&format!("Replay id: {id}\n"));
+    }
+    for player in &game.players {
+        text.push_str(&format!(
+            "\n{name} ({id})\n  Result: {result}{mvp}\n  Score: {score}, goals: {goals}, assists: {assists}, saves: {saves}, shots: {shots}\n",
+            name = player.name,
+            id = player.id,
+            result = player.outcome.map_or("none", Outcome::as_str),
+            mvp = match (player.mvp, game.mvp_guessed) {
+                (true, false) => ", MVP",
+                (true, true) => ", MVP (guessed)",
+                _ => "",
+            },
+            score = player.score,
+            goals = player.goals,
+            assists = player.assists,
+            saves = player.saves,
+            shots = player.shots,
+        ));
+        if let Some(deep) = player.deep {
+            text.push_str(&format!("  {deep:?}\n"));
+        }
+    }
+    text
+}
+
+/// When a file was last written, for replays that do not say when they were played.
+fn file_date(path: &Path) -> Option<DateTime<Local>> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::from(modified))
+}
+
+/// Reads a replay, with its network data if `deep_parse` is set. Corrupt network data is quite
+/// common, and falls back to only reading the header instead of losing the game.
+fn parse_rl(filename: &PathBuf, deep_parse: bool) -> Result<Replay> {
     let data = fs::read(filename)?;
-    let replay = boxcars::ParserBuilder::new(&data)
+    parse_replay(&data, filename, deep_parse)
+}
+
+/// Why a replay that was written could not be read.
+enum ReadError {
+    /// The game still has it locked or is still writing it, worth another try.
+    NotReady(anyhow::Error),
+    /// It was written in full but does not parse, e.g. because it is corrupt.
+    Unreadable(anyhow::Error),
+}
+
+/// Reads a replay that just settled. A replay that fails to parse while its size is still
+/// changing, or that is empty, is taken to be incomplete rather than corrupt.
+fn read_written_replay(path: &PathBuf, deep_parse: bool) -> Result<Replay, ReadError> {
+    let data = fs::read(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ReadError::Unreadable(e.into()),
+        // Windows refuses to share a file the game is still writing.
+        _ => ReadError::NotReady(e.into()),
+    })?;
+    if data.is_empty() {
+        return Err(ReadError::NotReady(anyhow!("the file is empty")));
+    }
+    parse_replay(&data, path, deep_parse).map_err(|e| {
+        let growing = fs::metadata(path).map_or(true, |meta| meta.len() != data.len() as u64);
+        if growing {
+            ReadError::NotReady(e)
+        } else {
+            ReadError::Unreadable(e)
+        }
+    })
+}
+
+/// Whether the file is a replay whose name matches `glob`, see `--replay-glob`.
+fn wanted_replay(path: &Path, glob: Option<&GlobMatcher>) -> bool {
+    is_replay(path)
+        && glob.is_none_or(|glob| path.file_name().is_some_and(|name| glob.is_match(name)))
+}
+
+/// Whether the file is a replay, also when it is gzipped (`.replay.gz`).
+fn is_replay(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    name.ends_with(".replay") || name.ends_with(".replay.gz")
+}
+
+/// Parses a replay that is already in memory. Gzipped ones, going by `filename`, are unpacked
+/// first, otherwise `filename` is only for the logs.
+fn parse_replay(data: &[u8], filename: &Path, deep_parse: bool) -> Result<Replay> {
+    let unpacked;
+    let data = if filename
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+    {
+        let mut buf = Vec::new();
+        GzDecoder::new(data)
+            .read_to_end(&mut buf)
+            .with_context(|| format!("Could not unpack {}", filename.to_string_lossy()))?;
+        unpacked = buf;
+        &unpacked
+    } else {
+        data
+    };
+    if deep_parse {
+        match boxcars::ParserBuilder::new(data)
+            .must_parse_network_data()
+            .parse()
+        {
+            Ok(replay) => return Ok(replay),
+            Err(e @ boxcars::ParseError::NetworkError(_)) => warn!(
+                "Network data of {} is corrupt, only reading the header: {}",
+                filename.to_string_lossy(),
+                e
+            ),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let replay = boxcars::ParserBuilder::new(data)
         .never_parse_network_data()
         .parse()?;
     Ok(replay)
 }
+
+/// The first of the usual replay folders for this platform that exists, see [`default_locations`].
+fn default_location() -> Option<PathBuf> {
+    default_locations()
+        .into_iter()
+        .find(|candidate| candidate.is_dir())
+}
+
+/// Bakkesmod's replay folder. The real roaming AppData folder is asked from the OS, so it also works
+/// when AppData is redirected (e.g. by OneDrive). The path under the user folder is kept as a fallback.
+#[cfg(windows)]
+fn default_locations() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(appdata) = dirs::config_dir() {
+        candidates.push(bakkesmod_replays(&appdata));
+    }
+    candidates.push(bakkesmod_replays(&PathBuf::from(format!(
+        r"C:\Users\{}\AppData\Roaming",
+        whoami::username()
+    ))));
+    candidates
+}
+
+/// Bakkesmod's replay folder in the Proton prefix of the Steam version, for the usual places
+/// Steam is installed in (native, the old symlink and Flatpak).
+#[cfg(target_os = "linux")]
+fn default_locations() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    [
+        ".steam/steam",
+        ".local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+    ]
+    .into_iter()
+    .map(|steam| {
+        bakkesmod_replays(
+            &home
+                .join(steam)
+                .join("steamapps/compatdata/252950/pfx/drive_c/users/steamuser/AppData/Roaming"),
+        )
+    })
+    .collect()
+}
+
+/// There is no Bakkesmod on macOS, but the game saves replays in its own folder.
+#[cfg(target_os = "macos")]
+fn default_locations() -> Vec<PathBuf> {
+    dirs::data_dir()
+        .map(|support| support.join("Rocket League/TAGame/Demos"))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+fn default_locations() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Where Bakkesmod keeps replays in a roaming AppData folder.
+#[cfg(any(windows, target_os = "linux"))]
+fn bakkesmod_replays(appdata: &Path) -> PathBuf {
+    appdata
+        .join("bakkesmod")
+        .join("bakkesmod")
+        .join("data")
+        .join("replays")
+}
+
+/// Parses `--since`, either an RFC 3339 date or a duration before now.
+fn parse_since(value: &str) -> Result<DateTime<Local>, String> {
+    parse_since_at(value, Local::now())
+}
+
+fn parse_since_at(value: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Local));
+    }
+    let ago = humantime::parse_duration(value).map_err(|_| {
+        format!("expected a date like 2023-01-02T15:04:05+01:00 or a duration like 2h, got {value}")
+    })?;
+    chrono::Duration::from_std(ago)
+        .ok()
+        .and_then(|ago| now.checked_sub_signed(ago))
+        .ok_or_else(|| format!("{value} is too long ago"))
+}
+
+/// `--form-decay` takes a share of the latest game, which has to count for something.
+fn parse_decay(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(decay) if decay > 0.0 && decay <= 1.0 => Ok(decay),
+        _ => Err(format!(
+            "expected a number above 0 and at most 1, got {value}"
+        )),
+    }
+}
+
+/// File names are matched ignoring case, like the `.replay` extension.
+fn parse_glob(value: &str) -> Result<GlobMatcher, String> {
+    GlobBuilder::new(value)
+        .case_insensitive(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| e.to_string())
+}
+
+/// Discord only shows pictures it can fetch itself, so anything but an http(s) url is refused.
+fn parse_image_url(value: &str) -> Result<String, String> {
+    let uri: hyper::Uri = value
+        .parse()
+        .map_err(|e| format!("{value} is not a valid url: {e}"))?;
+    match (uri.scheme_str(), uri.host()) {
+        (Some("http" | "https"), Some(_)) => Ok(value.to_string()),
+        _ => Err(format!("expected an http or https url, got {value}")),
+    }
+}
+
+/// Whether the game was played before `since`. Games without a date are kept.
+fn played_before(game: &Game, since: Option<DateTime<Local>>) -> bool {
+    matches!((game.played_at, since), (Some(played_at), Some(since)) if played_at < since)
+}
+
+/// Sends `replays` to the main loop one at a time, `delay` apart, and ends the session after.
+fn simulate(replays: Vec<PathBuf>, delay: Duration, tx: Sender<Message>) {
+    std::thread::spawn(move || {
+        for p in replays {
+            if tx.send(Message::Replay(p)).is_err() {
+                return;
+            }
+            std::thread::sleep(delay);
+        }
+        // Games are held back for up to SEND_INTERVAL, give the last of them time to go out.
+        std::thread::sleep(SEND_INTERVAL.saturating_sub(delay) + SETTLE_TIME);
+        let _ = tx.send(Message::Shutdown);
+    });
+}
+
+/// Replays in `locations` (and their subfolders if `recursive` is set) modified within `window`,
+/// oldest first. Only replays matching `glob` are returned, when there is one.
+fn backfill_replays(
+    locations: &[PathBuf],
+    window: Duration,
+    recursive: bool,
+    glob: Option<&GlobMatcher>,
+) -> Vec<PathBuf> {
+    let mut replays: Vec<(SystemTime, PathBuf)> = Vec::new();
+    let mut folders: Vec<PathBuf> = locations.to_vec();
+    while let Some(folder) = folders.pop() {
+        let Ok(entries) = fs::read_dir(&folder) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let p = entry.path();
+            if recursive && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                folders.push(p);
+                continue;
+            }
+            if !wanted_replay(&p, glob) {
+                continue;
+            }
+            let Ok(modified) = entry.metadata().and_then(|m| m.modified()) else {
+                continue;
+            };
+            if modified.elapsed().is_ok_and(|age| age <= window) {
+                replays.push((modified, p));
+            }
+        }
+    }
+    replays.sort_unstable();
+    replays.into_iter().map(|(_, p)| p).collect()
+}
+
+/// Pulls the stats of every player out of a replay. Freeplay, training and workshop maps also
+/// end up in the replay folder, these have no player stats or a match type of their own and
+/// give nothing.
+fn read_game(replay: &Replay) -> Result<Option<Game>> {
+    let match_type = replay.properties.iter().find_map(|(key, prop)| match prop {
+        HeaderProp::Name(v) | HeaderProp::Str(v) if key == "MatchType" => Some(v.as_str()),
+        _ => None,
+    });
+    if match_type.is_some_and(|match_type| NOT_A_MATCH.contains(&match_type)) {
+        return Ok(None);
+    }
+    let Some((_, stats)) = replay.properties.iter().find(|(s, _)| s == "PlayerStats") else {
+        return Ok(None);
+    };
+    let HeaderProp::Array(stats) = stats else {
+        return Err(anyhow!("Playerstats of replay is not a list"));
+    };
+
+    let team0_score = replay
+        .properties
+        .iter()
+        .find(|(s, _)| s == "Team0Score")
+        .map(|(_, v)| v.as_i32().unwrap_or_default())
+        .unwrap_or_default();
+    let team1_score = replay
+        .properties
+        .iter()
+        .find(|(s, _)| s == "Team1Score")
+        .map(|(_, v)| v.as_i32().unwrap_or_default())
+        .unwrap_or_default();
+    let forfeit = forfeited(replay);
+    if forfeit && team0_score == team1_score {
+        warn!("Game was forfeited on an even score, not counting a result");
+    }
+
+    let mut game = Game {
+        playlist: playlist(replay),
+        forfeit,
+        team_scores: (team0_score, team1_score),
+        played_at: match_date(replay),
+        duration: replay
+            .properties
+            .iter()
+            .find(|(s, _)| s == "TotalSecondsPlayed")
+            .and_then(|(_, v)| v.as_float()),
+        mvp_guessed: false,
+        // Only online games can be ranked, which of them were is in the network data.
+        ranked: match match_type {
+            Some("Online") | None => deep::ranked(replay),
+            Some(_) => Some(false),
+        },
+        replay_id: replay.properties.iter().find_map(|(key, prop)| match prop {
+            HeaderProp::Str(v) if key == "Id" => Some(v.clone()),
+            _ => None,
+        }),
+        players: Vec::new(),
+    };
+    let deep_stats = deep::deep_stats(replay);
+    let skill_tiers = deep::skill_tiers(replay);
+    let mut has_mvp_flag = false;
+    let mut has_team = false;
+    for player_stat in stats {
+        let mut name: Option<String> = None;
+        let mut platform: Option<String> = None;
+        let mut online_id: u64 = 0;
+        let mut score: usize = 0;
+        let mut goals: usize = 0;
+        let mut assists: usize = 0;
+        let mut saves: usize = 0;
+        let mut shots: usize = 0;
+        // Some older or modded replays leave the team out, see below.
+        let mut team: Option<usize> = None;
+        let mut mvp = false;
+        for (key, prop) in player_stat {
+            match (key.as_str(), prop) {
+                ("Name", HeaderProp::Str(v)) => name = Some(v.to_string()),
+                ("Platform", HeaderProp::Byte { kind, value }) => {
+                    platform = Some(value.as_ref().unwrap_or(kind).clone())
+                }
+                ("OnlineID", HeaderProp::QWord(v)) => online_id = *v,
+                ("Score", prop) => score = count(key, prop),
+                ("Goals", prop) => goals = count(key, prop),
+                ("Assists", prop) => assists = count(key, prop),
+                ("Saves", prop) => saves = count(key, prop),
+                ("Shots", prop) => shots = count(key, prop),
+                ("Team", HeaderProp::Int(v)) => {
+                    has_team = true;
+                    team = Some(*v as usize);
+                }
+                ("bMVP", HeaderProp::Bool(v)) => {
+                    has_mvp_flag = true;
+                    mvp = *v;
+                }
+                _ => {}
+            }
+        }
+
+        // Bots and the odd player have no name, which would lump them all together under "".
+        // They go under their team instead, numbered if a team has several.
+        let name = match name.filter(|name| !name.trim().is_empty()) {
+            Some(name) => name,
+            None => {
+                let label = match team {
+                    Some(team) => format!("Player (team {team})"),
+                    None => "Player (no team)".to_string(),
+                };
+                let taken = game
+                    .players
+                    .iter()
+                    .filter(|player| player.name.starts_with(&label))
+                    .count();
+                if taken == 0 {
+                    label
+                } else {
+                    format!("{label} {}", taken + 1)
+                }
+            }
+        };
+        game.players.push(PlayerGame {
+            id: player_id(&name, platform.as_deref(), online_id),
+            score,
+            goals,
+            assists,
+            saves,
+            shots,
+            goal_differential: match team {
+                Some(0) => (team0_score - team1_score) as i64,
+                Some(1) => (team1_score - team0_score) as i64,
+                _ => 0,
+            },
+            outcome: team.and_then(|team| outcome(team, team0_score, team1_score, forfeit)),
+            team,
+            mvp,
+            // Players the network data says nothing about get no deep stats rather than zeros.
+            deep: deep_stats
+                .as_ref()
+                .and_then(|stats| stats.get(&name).copied()),
+            rank: skill_tiers.get(&name).copied(),
+            name,
+        });
+    }
+    if !has_team && !game.players.is_empty() {
+        warn!("Replay does not say who played on which team, not counting a result for anyone");
+    }
+
+    // Not every replay says who the MVP was, in which case it goes to the winning team's top scorer.
+    if !has_mvp_flag {
+        if let Some(top) = game
+            .players
+            .iter_mut()
+            .filter(|player| player.outcome == Some(Outcome::Win))
+            .max_by_key(|player| player.score)
+        {
+            top.mvp = true;
+            game.mvp_guessed = true;
+        }
+    }
+
+    Ok(Some(game))
+}
+
+/// Match types of replays that were not a game against anyone.
+const NOT_A_MATCH: &[&str] = &["Training", "Freeplay", "Workshop", "Tutorial"];
+
+/// A stat that counts something, which is usually an `Int` but not in every replay. Anything
+/// that is not a number is logged and counted as 0, as are negative numbers.
+fn count(key: &str, prop: &HeaderProp) -> usize {
+    let count = match prop {
+        HeaderProp::Int(v) => usize::try_from(*v).ok(),
+        HeaderProp::QWord(v) => usize::try_from(*v).ok(),
+        HeaderProp::Float(v) if v.is_finite() && *v >= 0.0 => Some(v.round() as usize),
+        HeaderProp::Byte { value: Some(v), .. } => v.parse().ok(),
+        _ => None,
+    };
+    count.unwrap_or_else(|| {
+        debug!("{} of a player is not a count ({:?}), using 0", key, prop);
+        0
+    })
+}
+
+/// The result of a game for a player on `team`. A forfeited game is won by whoever was ahead,
+/// but a forfeit on an even score does not tell us who gave up, so nobody gets a result for it.
+/// Neither does a player on a team other than 0 or 1.
+fn outcome(team: usize, team0_score: i32, team1_score: i32, forfeit: bool) -> Option<Outcome> {
+    let (own, other) = match team {
+        0 => (team0_score, team1_score),
+        1 => (team1_score, team0_score),
+        _ => return None,
+    };
+    match own.cmp(&other) {
+        std::cmp::Ordering::Greater => Some(Outcome::Win),
+        std::cmp::Ordering::Less => Some(Outcome::Loss),
+        std::cmp::Ordering::Equal if forfeit => None,
+        std::cmp::Ordering::Equal => Some(Outcome::Draw),
+    }
+}
+
+/// Players are keyed on their platform and online id, so a name change does not split their stats.
+/// Not every platform fills in the id (it is 0 for Epic), in which case we fall back to the name.
+fn player_id(name: &str, platform: Option<&str>, online_id: u64) -> String {
+    match platform {
+        Some(platform) if online_id != 0 => format!("{platform}:{online_id}"),
+        _ => name.to_string(),
+    }
+}
+
+/// Whether the game ended by one team forfeiting. The header property has had a couple of names
+/// over the years, so any of them counts.
+fn forfeited(replay: &Replay) -> bool {
+    replay.properties.iter().any(|(key, prop)| {
+        matches!(key.as_str(), "bForfeit" | "bGameEndedInForfeit")
+            && matches!(prop, HeaderProp::Bool(true))
+    })
+}
+
+/// When the match was played, in local time. Bakkesmod writes dates like `2023-01-02 15-04-05`.
+fn match_date(replay: &Replay) -> Option<DateTime<Local>> {
+    let date = replay
+        .properties
+        .iter()
+        .find_map(|(key, prop)| match prop {
+            HeaderProp::Str(date) if key == "Date" => Some(date),
+            _ => None,
+        })?;
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H-%M-%S")
+        .ok()?
+        .and_local_timezone(Local)
+        .earliest()
+}
+
+/// Names the playlist from the match type and team size, e.g. "Online 2v2".
+fn playlist(replay: &Replay) -> String {
+    let prop = |name: &str| {
+        replay
+            .properties
+            .iter()
+            .find(|(s, _)| s == name)
+            .map(|(_, v)| v)
+    };
+    let match_type = prop("MatchType").and_then(|v| match v {
+        HeaderProp::Name(v) | HeaderProp::Str(v) => Some(v.as_str()),
+        _ => None,
+    });
+    let team_size = prop("TeamSize").and_then(|v| v.as_i32());
+    match (match_type, team_size) {
+        (Some(match_type), Some(size)) => format!("{match_type} {size}v{size}"),
+        (Some(match_type), None) => match_type.to_string(),
+        (None, Some(size)) => format!("{size}v{size}"),
+        (None, None) => "Unknown".to_string(),
+    }
+}
+
+/// Writes the tally to the state file, json output and stats server, if they are enabled.
+/// The json output is anonymized with `--anonymize`, the stats server does that itself.
+fn write_tally(args: &Args, tally: &Tally, live: Option<&Mutex<Tally>>) {
+    if let Some(Ok(mut live)) = live.map(Mutex::lock) {
+        live.clone_from(tally);
+    }
+    if let Some(path) = &args.state_file {
+        if let Err(e) = write_atomic(path, &serde_json::to_string(tally).unwrap_or_default()) {
+            error!("Failed to write state file: {:?}", e);
+        }
+    }
+    if let Some(path) = &args.json_out {
+        let anonymized = args.anonymize.then(|| tally.anonymized());
+        let json =
+            serde_json::to_string_pretty(anonymized.as_ref().unwrap_or(tally)).unwrap_or_default();
+        if let Err(e) = write_atomic(path, &json) {
+            error!("Failed to write json output: {:?}", e);
+        }
+    }
+}
+
+/// Logs the game to the database and the csv output, if there are any. The tally does not
+/// depend on them, so failing is not fatal.
+fn record_game(db: Option<&mut Db>, csv_log: Option<&mut CsvLog>, replay: &Path, game: &Game) {
+    if let Some(Err(e)) = db.map(|db| db.record(replay, game)) {
+        error!("Failed to write game to database: {:?}", e);
+    }
+    if let Some(Err(e)) = csv_log.map(|csv_log| csv_log.record(replay, game)) {
+        error!("Failed to write game to csv output: {:?}", e);
+    }
+}
+
+fn load_tally(path: &PathBuf) -> Result<Tally> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Could not read state file {}", path.to_string_lossy()))?;
+    let mut tally: Tally = serde_json::from_str(&data)
+        .with_context(|| format!("State file {} is not valid", path.to_string_lossy()))?;
+    tally.fill_missing_names();
+    tally.number_players();
+    Ok(tally)
+}
+
+/// Writes to a temporary file next to `path` and renames it into place,
+/// so a crash mid-write never leaves a half-written file behind.
+fn write_atomic(path: &PathBuf, contents: &str) -> Result<()> {
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            per_playlist: false,
+            trend_window: 5,
+            margins: (1, 5),
+            premade_share: 0.5,
+            sort_by: SortBy::Score,
+            presence: Presence {
+                min_games: 3,
+                ratio: 0.5,
+                players: Vec::new(),
+            },
+            template: Template::parse(&columns_template(&[]), PLAYER_PLACEHOLDERS).unwrap(),
+            aliases: Aliases::default(),
+            ranked_only: false,
+            deep_parse: false,
+            team_names: ("Blue".to_string(), "Orange".to_string()),
+            anonymize: false,
+            baseline: None,
+            max_players: None,
+            group_by_team: false,
+            me: None,
+            exclude: Vec::new(),
+            form_decay: 0.3,
+            rating: (1000.0, 32.0),
+        }
+    }
+
+    fn replay(properties: Vec<(&str, HeaderProp)>) -> Replay {
+        Replay {
+            header_size: 0,
+            header_crc: 0,
+            major_version: 868,
+            minor_version: 20,
+            net_version: Some(10),
+            game_type: "TAGame.Replay_Soccar_TA".to_string(),
+            properties: properties
+                .into_iter()
+                .map(|(key, prop)| (key.to_string(), prop))
+                .collect(),
+            content_size: 0,
+            content_crc: 0,
+            network_frames: None,
+            levels: Vec::new(),
+            keyframes: Vec::new(),
+            debug_info: Vec::new(),
+            tick_marks: Vec::new(),
+            packages: Vec::new(),
+            objects: Vec::new(),
+            names: Vec::new(),
+            class_indices: Vec::new(),
+            net_cache: Vec::new(),
+        }
+    }
+
+    /// A 1v1 between alice on team 0 and bob on team 1, who score a goal per point.
+    fn game(alice: i32, bob: i32) -> Replay {
+        let player = |name: &str, team: i32, goals: i32| {
+            vec![
+                ("Name".to_string(), HeaderProp::Str(name.to_string())),
+                ("Team".to_string(), HeaderProp::Int(team)),
+                ("Score".to_string(), HeaderProp::Int(100 * goals)),
+                ("Goals".to_string(), HeaderProp::Int(goals)),
+                ("Shots".to_string(), HeaderProp::Int(goals + 1)),
+            ]
+        };
+        replay(vec![
+            ("Team0Score", HeaderProp::Int(alice)),
+            ("Team1Score", HeaderProp::Int(bob)),
+            ("MatchType", HeaderProp::Name("Online".to_string())),
+            ("TeamSize", HeaderProp::Int(1)),
+            (
+                "PlayerStats",
+                HeaderProp::Array(vec![player("alice", 0, alice), player("bob", 1, bob)]),
+            ),
+        ])
+    }
+
+    /// A replay with only a header and an empty body, made for these tests: an online 2v2 that
+    /// alice and bob (team 0) won 3-1 against carol and dave.
+    fn sample_replay() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/online-2v2.replay")
+    }
+
+    #[test]
+    fn parse_rl_sample_replay() {
+        let replay = parse_rl(&sample_replay(), false).unwrap();
+        let stats = replay
+            .properties
+            .iter()
+            .find(|(key, _)| key == "PlayerStats")
+            .and_then(|(_, prop)| prop.as_array())
+            .unwrap();
+        assert_eq!(stats.len(), 4);
+
+        let game = read_game(&replay).unwrap().unwrap();
+        assert_eq!(game.playlist, "Online 2v2");
+        assert_eq!(game.team_scores, (3, 1));
+        assert_eq!(game.duration, Some(312.5));
+        assert_eq!(game.players[1].id, "OnlinePlatform_Steam:76561198000000000");
+        assert!(game.players[0].mvp && !game.mvp_guessed);
+    }
+
+    #[test]
+    fn parse_rl_gzipped_replay() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&fs::read(sample_replay()).unwrap())
+            .unwrap();
+        let path =
+            std::env::temp_dir().join(format!("rl-session-{}.Replay.gz", std::process::id()));
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        assert!(is_replay(&path));
+        assert!(!is_replay(Path::new("notes.gz")));
+
+        let game = read_game(&parse_rl(&path, false).unwrap())
+            .unwrap()
+            .unwrap();
+        let expected = read_game(&parse_rl(&sample_replay(), false).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(game.team_scores, expected.team_scores);
+        assert_eq!(game.replay_id, expected.replay_id);
+        assert_eq!(game.players.len(), 4);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parse_rl_deep_sample_replay() {
+        let replay = parse_rl(&sample_replay(), true).unwrap();
+        assert_eq!(replay.properties.len(), 7);
+        assert!(deep::deep_stats(&replay).is_some_and(|stats| stats.is_empty()));
+    }
+
+    #[test]
+    fn players_missing_from_deep_stats() {
+        let settings = Settings {
+            deep_parse: true,
+            ..settings()
+        };
+        let replay = parse_rl(&sample_replay(), true).unwrap();
+        let mut tally = Tally::default();
+        let game = apply_replay(&mut tally, &replay, &settings)
+            .unwrap()
+            .unwrap();
+        assert!(game.players.iter().all(|player| player.deep.is_none()));
+        let stats = &tally.player_stats[&game.players[0].id];
+        assert!(stats.deep.is_none());
+        let rendered = render_player(stats, &settings).stats;
+        assert!(!rendered.contains("- Demos:"));
+        assert!(rendered.ends_with(
+            "- Demos, boost and touches: not available, the network data could not be read\n"
+        ));
+    }
+
+    #[test]
+    fn validate_sample_replay() {
+        let replay = parse_rl(&sample_replay(), false).unwrap();
+        let game = read_game(&replay).unwrap().unwrap();
+        let text = render_validation(&game, ("Blue", "Orange"));
+        assert!(text.starts_with("Playlist: Online 2v2\nScore: Blue 3-1 Orange\n"));
+        assert!(text.contains("Length: 312.5s\n"));
+        assert!(text.contains("\nalice ("));
+        assert!(text.contains("  Result: win, MVP\n"));
+        assert_eq!(text.matches("  Result: ").count(), 4);
+    }
+
+    #[test]
+    fn replays_from_zip() {
+        use std::io::Write;
+        let path = std::env::temp_dir().join(format!("rl-session-{}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("batch/online-2v2.replay", options).unwrap();
+        zip.write_all(&fs::read(sample_replay()).unwrap()).unwrap();
+        zip.start_file("notes.txt", options).unwrap();
+        zip.write_all(b"gg").unwrap();
+        zip.finish().unwrap();
+
+        let mut games = Vec::new();
+        let replays = archive::read_replays(&path, |name, data| {
+            let replay = parse_replay(data, Path::new(name), false).unwrap();
+            games.push((name.to_string(), read_game(&replay).unwrap().unwrap()));
+        })
+        .unwrap();
+        assert_eq!(replays, 1);
+        assert_eq!(games[0].0, "batch/online-2v2.replay");
+        assert_eq!(games[0].1.team_scores, (3, 1));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parse_rl_broken_files() {
+        let data = fs::read(sample_replay()).unwrap();
+        let dir = std::env::temp_dir();
+        let truncated = dir.join(format!(
+            "rl-session-truncated-{}.replay",
+            std::process::id()
+        ));
+        let garbage = dir.join(format!("rl-session-garbage-{}.replay", std::process::id()));
+        fs::write(&truncated, &data[..data.len() / 2]).unwrap();
+        fs::write(&garbage, [0xff; 64]).unwrap();
+
+        assert!(parse_rl(&truncated, false).is_err());
+        assert!(parse_rl(&garbage, false).is_err());
+        assert!(parse_rl(&dir.join("rl-session-missing.replay"), false).is_err());
+        fs::remove_file(truncated).unwrap();
+        fs::remove_file(garbage).unwrap();
+    }
+
+    #[test]
+    fn written_replay_not_ready_or_corrupt() {
+        let path =
+            std::env::temp_dir().join(format!("rl-session-written-{}.replay", std::process::id()));
+        fs::write(&path, []).unwrap();
+        assert!(matches!(
+            read_written_replay(&path, false),
+            Err(ReadError::NotReady(_))
+        ));
+        // Cut off, with nothing more coming: it will not get better by waiting.
+        let data = fs::read(sample_replay()).unwrap();
+        fs::write(&path, &data[..data.len() / 2]).unwrap();
+        assert!(matches!(
+            read_written_replay(&path, false),
+            Err(ReadError::Unreadable(_))
+        ));
+        fs::write(&path, &data).unwrap();
+        assert!(read_written_replay(&path, false).is_ok());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            read_written_replay(&path, false),
+            Err(ReadError::Unreadable(_))
+        ));
+    }
+
+    #[test]
+    fn csv_out_appends_rows() {
+        let path = std::env::temp_dir().join(format!("rl-session-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let game = read_game(&game(2, 1)).unwrap().unwrap();
+        CsvLog::open(&path)
+            .unwrap()
+            .record(Path::new("a.replay"), &game)
+            .unwrap();
+        // Opening it again must not repeat the header.
+        CsvLog::open(&path)
+            .unwrap()
+            .record(Path::new("b.replay"), &game)
+            .unwrap();
+
+        let data = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = data.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("timestamp,replay_file,playlist,"));
+        assert!(lines[1].contains(",a.replay,Online 1v1,2,1,"));
+        assert!(lines[1].contains(",alice,win,"));
+        assert!(lines[4].contains(",b.replay,"));
+        assert!(lines[4].contains(",bob,loss,"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_cache_until_changed() {
+        let dir = std::env::temp_dir().join(format!("rl-session-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let replay = dir.join("a.replay");
+        fs::write(&replay, "not really a replay").unwrap();
+        let game = read_game(&game(2, 1)).unwrap().unwrap();
+
+        let mut cache = ReplayCache::load(&dir.join("cache.json"));
+        assert!(cache.get(&replay, false).is_none());
+        cache.insert(&replay, false, Some(&game));
+        cache.save().unwrap();
+        let cache = ReplayCache::load(&dir.join("cache.json"));
+        let cached = cache.get(&replay, false).unwrap().unwrap();
+        assert_eq!(cached.team_scores, (2, 1));
+        assert_eq!(cached.players[0].outcome, Some(Outcome::Win));
+        // It was read without the network data, which --deep-parse wants.
+        assert!(cache.get(&replay, true).is_none());
+
+        fs::write(&replay, "a different replay").unwrap();
+        assert!(cache.get(&replay, false).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn metrics_text() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(2, 1), &settings()).unwrap();
+        // Another alice, and someone who was not in the game, who is not tracked.
+        let alice = tally.player_stats["alice"].clone();
+        tally
+            .player_stats
+            .insert("alice-2".to_string(), alice.clone());
+        let carol = PlayerStats {
+            name: "carol".to_string(),
+            times_seen: 0,
+            ..alice
+        };
+        tally.player_stats.insert("carol".to_string(), carol);
+        let text = metrics::render(&tally, &settings());
+        assert!(text.starts_with("# HELP rl_games_total Games played.\n# TYPE rl_games_total counter\nrl_games_total 1\n"));
+        assert!(text.contains(concat!(
+            "\nrl_player_goals{id=\"alice\",player=\"alice\"} 2\n",
+            "rl_player_goals{id=\"alice-2\",player=\"alice\"} 2\n",
+            "rl_player_goals{id=\"bob\",player=\"bob\"} 1\n",
+        )));
+        assert!(!text.contains("carol"));
+        assert!(text.contains("\n# TYPE rl_webhook_failures_total counter\n"));
+    }
+
+    #[test]
+    fn metrics_text_anonymized() {
+        let settings = Settings {
+            anonymize: true,
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(2, 1), &settings).unwrap();
+        for tally in [tally.clone(), tally.anonymized()] {
+            let text = metrics::render(&tally, &settings);
+            assert!(text.contains(
+                "\nrl_player_goals{player=\"Player 1\"} 2\nrl_player_goals{player=\"Player 2\"} 1\n"
+            ));
+            assert!(!text.contains("alice") && !text.contains("bob") && !text.contains("id="));
+        }
+    }
+
+    #[test]
+    fn game_event_line() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(1, 0), &settings()).unwrap();
+        let game = apply_replay(&mut tally, &game(2, 1), &settings())
+            .unwrap()
+            .unwrap();
+        let line = events::game_event(Path::new("a.replay"), &game, &tally, &settings());
+        assert!(!line.contains('\n'));
+        let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["replay_file"], "a.replay");
+        assert_eq!(event["games_played"], 2);
+        let alice = &event["players"][0];
+        assert_eq!(
+            (&alice["id"], &alice["result"]),
+            (&"alice".into(), &"win".into())
+        );
+        assert_eq!(
+            (&alice["goals"], &alice["totals"]["goals"][0]),
+            (&2.into(), &3.into())
+        );
+    }
+
+    #[test]
+    fn announce_rank_changes() {
+        let mut tally = Tally::default();
+        let ranked = |tiers: [Option<u8>; 2]| {
+            let mut game = read_game(&game(2, 1)).unwrap().unwrap();
+            for (player, tier) in game.players.iter_mut().zip(tiers) {
+                player.rank = tier;
+            }
+            game
+        };
+        let first = ranked([Some(13), None]);
+        tally.add_game(&first, &settings());
+        assert!(render_rank_changes(&tally, &[first], &settings()).is_empty());
+
+        let second = ranked([Some(14), Some(9)]);
+        tally.add_game(&second, &settings());
+        assert_eq!(
+            render_rank_changes(&tally, std::slice::from_ref(&second), &settings()),
+            "alice promoted to Diamond II!\n\n"
+        );
+        let third = ranked([Some(14), Some(8)]);
+        tally.add_game(&third, &settings());
+        assert!(render_game(&tally, &third, &settings())
+            .header
+            .ends_with("bob demoted to Gold II\n\n"));
+        assert_eq!(rank_name(22).as_deref(), Some("Supersonic Legend"));
+        assert_eq!(rank_name(0), None);
+    }
+
+    #[test]
+    fn heartbeat_message() {
+        let mut tally = Tally::default();
+        assert_eq!(render_heartbeat(&tally), "Still tracking, no games yet");
+        apply_replay(&mut tally, &game(1, 0), &settings()).unwrap();
+        assert_eq!(render_heartbeat(&tally), "Still tracking, 1 game so far");
+        apply_replay(&mut tally, &game(0, 2), &settings()).unwrap();
+        assert_eq!(render_heartbeat(&tally), "Still tracking, 2 games so far");
+    }
+
+    #[test]
+    fn exit_summary_numbers() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(1, 0), &settings()).unwrap();
+        apply_replay(&mut tally, &game(0, 2), &settings()).unwrap();
+        let json = events::exit_summary(&tally, &settings(), Local::now());
+        let summary: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(summary["games_played"], 2);
+        assert_eq!(summary["win_rate"], 50);
+        let bob = &summary["players"][0];
+        assert_eq!((&bob["name"], &bob["goals"]), (&"bob".into(), &2.into()));
+        assert_eq!((&bob["wins"], &bob["losses"]), (&1.into(), &1.into()));
+    }
+
+    #[test]
+    fn anonymized_events() {
+        let settings = Settings {
+            anonymize: true,
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        let game = apply_replay(&mut tally, &game(2, 1), &settings)
+            .unwrap()
+            .unwrap();
+        let line = events::game_event(Path::new("a.replay"), &game, &tally, &settings);
+        let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["players"][1]["id"], "Player 2");
+        let summary = events::exit_summary(&tally, &settings, Local::now());
+        for json in [line, summary] {
+            assert!(!json.contains("alice") && !json.contains("bob"));
+        }
+    }
+
+    #[test]
+    fn apply_replay_adds_players() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(3, 1), &settings()).unwrap();
+        apply_replay(&mut tally, &game(0, 2), &settings()).unwrap();
+
+        assert_eq!(tally.games_played, 2);
+        let alice = &tally.player_stats["alice"];
+        assert_eq!((alice.wins, alice.losses, alice.draws), (1, 1, 0));
+        assert_eq!(alice.goals, (3, 0));
+        assert_eq!(alice.shots, (5, 1));
+        assert_eq!(alice.goal_differential, (0, -2));
+        assert_eq!((alice.best.score, alice.best.goals), (300, 3));
+        let bob = &tally.player_stats["bob"];
+        assert_eq!((bob.wins, bob.losses, bob.draws), (1, 1, 0));
+        assert_eq!(bob.mvps, 1);
+        assert_eq!((bob.best.score, bob.best.goals), (200, 2));
+    }
+
+    #[test]
+    fn apply_replay_without_player_stats() {
+        let mut tally = Tally::default();
+        let replay = replay(vec![("Team0Score", HeaderProp::Int(1))]);
+        assert!(apply_replay(&mut tally, &replay, &settings())
+            .unwrap()
+            .is_none());
+        assert_eq!(tally.games_played, 0);
+    }
+
+    #[test]
+    fn apply_replay_ranked_only() {
+        use boxcars::{
+            ActorId, Attribute, Frame, NetworkFrames, ObjectId, StreamId, UpdatedAttribute,
+        };
+        let in_playlist = |playlist: i32| {
+            let mut replay = game(2, 1);
+            replay.objects = vec!["ProjectX.GRI_X:ReplicatedGamePlaylist".to_string()];
+            replay.network_frames = Some(NetworkFrames {
+                frames: vec![Frame {
+                    time: 0.0,
+                    delta: 0.0,
+                    new_actors: Vec::new(),
+                    deleted_actors: Vec::new(),
+                    updated_actors: vec![UpdatedAttribute {
+                        actor_id: ActorId(0),
+                        stream_id: StreamId(0),
+                        object_id: ObjectId(0),
+                        attribute: Attribute::Int(playlist),
+                    }],
+                }],
+            });
+            replay
+        };
+        let mut private = game(2, 1);
+        private.properties[2].1 = HeaderProp::Name("Private".to_string());
+        let settings = Settings {
+            ranked_only: true,
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        for replay in [in_playlist(1), game(2, 1), private] {
+            assert!(apply_replay(&mut tally, &replay, &settings)
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(tally.games_played, 0);
+
+        let game = apply_replay(&mut tally, &in_playlist(11), &settings)
+            .unwrap()
+            .unwrap();
+        assert_eq!(game.ranked, Some(true));
+        assert_eq!(tally.games_played, 1);
+    }
+
+    #[test]
+    fn reset_keeps_counted_replays() {
+        let mut tally = Tally::default();
+        let mut replay = game(2, 1);
+        replay
+            .properties
+            .push(("Id".to_string(), HeaderProp::Str("ABC".to_string())));
+        apply_replay(&mut tally, &replay, &settings()).unwrap();
+        let now = Local::now();
+        tally.reset(now);
+        assert_eq!(tally.games_played, 0);
+        assert!(tally.player_stats.is_empty() && tally.history.is_empty());
+        assert_eq!(tally.started_at, Some(now));
+        assert!(apply_replay(&mut tally, &replay, &settings())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn apply_replay_skips_duplicates() {
+        let with_id = |id: &str| {
+            let mut replay = game(1, 0);
+            replay
+                .properties
+                .push(("Id".to_string(), HeaderProp::Str(id.to_string())));
+            replay
+        };
+        let mut tally = Tally::default();
+        assert!(apply_replay(&mut tally, &with_id("A1"), &settings())
+            .unwrap()
+            .is_some());
+        assert!(apply_replay(&mut tally, &with_id("A1"), &settings())
+            .unwrap()
+            .is_none());
+        assert_eq!(tally.games_played, 1);
+
+        // The replays seen survive a restart.
+        let mut resumed: Tally =
+            serde_json::from_str(&serde_json::to_string(&tally).unwrap()).unwrap();
+        assert!(apply_replay(&mut resumed, &with_id("A1"), &settings())
+            .unwrap()
+            .is_none());
+        assert!(apply_replay(&mut resumed, &with_id("B2"), &settings())
+            .unwrap()
+            .is_some());
+        // Without an id there is nothing to tell copies apart by.
+        apply_replay(&mut resumed, &game(1, 0), &settings()).unwrap();
+        apply_replay(&mut resumed, &game(1, 0), &settings()).unwrap();
+        assert_eq!(resumed.games_played, 4);
+    }
+
+    #[test]
+    fn spectated_games_with_me() {
+        let settings = Settings {
+            me: Some("alice".to_string()),
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(0, 1), &settings).unwrap();
+        let mut spectated = game(3, 0);
+        if let HeaderProp::Array(players) = &mut spectated.properties[4].1 {
+            players[0][0].1 = HeaderProp::Str("carol".to_string());
+        }
+        let spectated = apply_replay(&mut tally, &spectated, &settings)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tally.games_played, 1);
+        assert_eq!(tally.player_stats["carol"].wins, 1);
+        assert_eq!(tally.player_stats["bob"].times_seen, 2);
+        // bob won one and lost one, but the session went as alice's did.
+        assert_eq!(win_rate(&tally, &settings), 0);
+        let message = render_game(&tally, &spectated, &settings).header;
+        assert!(message.starts_with("## Spectated game finished (Online 1v1)\n"));
+    }
+
+    #[test]
+    fn form_weights_recent_games() {
+        let settings = Settings {
+            form_decay: 0.5,
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        let mut forms = Vec::new();
+        for goals in [2, 0, 4, 4] {
+            apply_replay(&mut tally, &game(goals, 1), &settings).unwrap();
+            forms.push(tally.player_stats["alice"].form.unwrap());
+        }
+        assert_eq!(forms, [200.0, 100.0, 250.0, 325.0]);
+        let stats = render_player(&tally.player_stats["alice"], &settings).stats;
+        assert!(stats.contains("250 per game, form 325 "));
+    }
+
+    #[test]
+    fn elo_ratings() {
+        let mut rating = Rating {
+            rating: 1000.0,
+            delta: 0.0,
+        };
+        let mut seen = Vec::new();
+        for outcome in [Outcome::Win, Outcome::Win, Outcome::Loss, Outcome::Draw] {
+            rating.update(outcome, (1000.0, 32.0));
+            seen.push((
+                (rating.rating * 100.0).round(),
+                (rating.delta * 100.0).round(),
+            ));
+        }
+        assert_eq!(
+            seen,
+            [
+                (101600.0, 1600.0),
+                (103126.0, 1526.0),
+                (101383.0, -1744.0),
+                (101319.0, -64.0)
+            ]
+        );
+
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(2, 1), &settings()).unwrap();
+        apply_replay(&mut tally, &game(2, 1), &settings()).unwrap();
+        let stats = render_player(&tally.player_stats["bob"], &settings()).stats;
+        assert!(stats.ends_with("- Rating: 969 (-15) in Online 1v1\n"));
+    }
+
+    #[test]
+    fn apply_replay_without_teams() {
+        let mut replay = game(2, 1);
+        if let HeaderProp::Array(players) = &mut replay.properties[4].1 {
+            for player in players {
+                player.retain(|(key, _)| key != "Team");
+            }
+        }
+        let mut tally = Tally::default();
+        let game = apply_replay(&mut tally, &replay, &settings())
+            .unwrap()
+            .unwrap();
+        assert!(game.players.iter().all(|player| player.outcome.is_none()));
+        assert!(!game.mvp_guessed);
+        let alice = &tally.player_stats["alice"];
+        assert_eq!((alice.wins, alice.losses, alice.goals.0), (0, 0, 2));
+        assert_eq!(tally.player_stats["bob"].goal_differential, (0, 0));
+    }
+
+    #[test]
+    fn read_game_other_number_types() {
+        let mut replay = game(2, 1);
+        if let HeaderProp::Array(players) = &mut replay.properties[4].1 {
+            players[0][2].1 = HeaderProp::QWord(250);
+            players[0][3].1 = HeaderProp::Float(2.0);
+            players[0].push((
+                "Saves".to_string(),
+                HeaderProp::Byte {
+                    kind: "Saves".to_string(),
+                    value: Some("4".to_string()),
+                },
+            ));
+            players[1][3].1 = HeaderProp::Int(-1);
+            players[1][4].1 = HeaderProp::Str("many".to_string());
+        }
+        let game = read_game(&replay).unwrap().unwrap();
+        let (alice, bob) = (&game.players[0], &game.players[1]);
+        assert_eq!((alice.score, alice.goals, alice.saves), (250, 2, 4));
+        assert_eq!((bob.goals, bob.shots), (0, 0));
+    }
+
+    #[test]
+    fn apply_replay_nameless_players() {
+        let player = |name: Option<&str>, team: i32, goals: i32| {
+            let mut stats = vec![
+                ("Team".to_string(), HeaderProp::Int(team)),
+                ("Goals".to_string(), HeaderProp::Int(goals)),
+            ];
+            if let Some(name) = name {
+                stats.push(("Name".to_string(), HeaderProp::Str(name.to_string())));
+            }
+            stats
+        };
+        let replay = replay(vec![
+            ("Team0Score", HeaderProp::Int(2)),
+            ("Team1Score", HeaderProp::Int(1)),
+            (
+                "PlayerStats",
+                HeaderProp::Array(vec![
+                    player(Some("alice"), 0, 2),
+                    player(None, 0, 0),
+                    player(Some(""), 1, 1),
+                    player(None, 1, 0),
+                ]),
+            ),
+        ]);
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &replay, &settings()).unwrap();
+
+        assert_eq!(tally.player_stats.len(), 4);
+        assert_eq!(tally.player_stats["Player (team 0)"].wins, 1);
+        assert_eq!(tally.player_stats["Player (team 1)"].goals, (1, 1));
+        assert_eq!(tally.player_stats["Player (team 1) 2"].losses, 1);
+    }
+
+    #[test]
+    fn apply_replay_skips_freeplay() {
+        let mut tally = Tally::default();
+        let mut freeplay = game(0, 0);
+        freeplay
+            .properties
+            .retain(|(key, _)| key != "Team0Score" && key != "Team1Score");
+        freeplay.properties[0].1 = HeaderProp::Name("Freeplay".to_string());
+        assert!(apply_replay(&mut tally, &freeplay, &settings())
+            .unwrap()
+            .is_none());
+        assert_eq!(tally.games_played, 0);
+        assert!(tally.player_stats.is_empty());
+    }
+
+    #[test]
+    fn render_game_message() {
+        let mut tally = Tally::default();
+        let game = apply_replay(&mut tally, &game(2, 1), &settings())
+            .unwrap()
+            .unwrap();
+        let message = render_game(&tally, &game, &settings()).to_markdown();
+        assert!(message.starts_with("## Game 1 finished (Online 1v1, nailbiter)\nBlue 2-1 Orange\n\nMVP: alice (top scorer of the winning team)\n\n### alice\n"));
+        assert!(message.contains("### bob\n*Played 1 games*\n- Wins/Losses/Draws: 0/1/0\n"));
+    }
+
+    #[test]
+    fn game_margins() {
+        let margin = |margins, (alice, bob)| {
+            let settings = Settings {
+                margins,
+                ..settings()
+            };
+            let game = apply_replay(&mut Tally::default(), &game(alice, bob), &settings)
+                .unwrap()
+                .unwrap();
+            settings.margin(&game)
+        };
+        assert_eq!(margin((2, 3), (1, 0)), Some("nailbiter"));
+        assert_eq!(margin((2, 3), (1, 3)), Some("nailbiter"));
+        assert_eq!(margin((2, 3), (4, 1)), Some("blowout"));
+        assert_eq!(margin((2, 4), (4, 1)), None);
+        assert_eq!(margin((2, 3), (2, 2)), None);
+        assert_eq!(margin((0, 0), (1, 0)), None);
+        assert_eq!(margin((0, 0), (9, 0)), None);
+    }
+
+    #[test]
+    fn guess_premade_games() {
+        let two_v_two = |team0: [&str; 2], team1: [&str; 2]| {
+            let player = |name: &str, team: i32| {
+                vec![
+                    ("Name".to_string(), HeaderProp::Str(name.to_string())),
+                    ("Team".to_string(), HeaderProp::Int(team)),
+                ]
+            };
+            replay(vec![
+                ("Team0Score", HeaderProp::Int(2)),
+                ("Team1Score", HeaderProp::Int(0)),
+                ("MatchType", HeaderProp::Name("Online".to_string())),
+                ("TeamSize", HeaderProp::Int(2)),
+                (
+                    "PlayerStats",
+                    HeaderProp::Array(vec![
+                        player(team0[0], 0),
+                        player(team0[1], 0),
+                        player(team1[0], 1),
+                        player(team1[1], 1),
+                    ]),
+                ),
+            ])
+        };
+        let mut tally = Tally::default();
+        let mut parties = Vec::new();
+        for team in [
+            ["alice", "bob"],
+            ["alice", "bob"],
+            ["alice", "bob"],
+            ["alice", "random"],
+        ] {
+            let opponents = [format!("x{}", parties.len()), format!("y{}", parties.len())];
+            let game = apply_replay(
+                &mut tally,
+                &two_v_two(team, [&opponents[0], &opponents[1]]),
+                &settings(),
+            )
+            .unwrap()
+            .unwrap();
+            parties.push(guess_party(&tally, &game, &settings()));
+        }
+        // Too early to tell who the group is for the first two.
+        assert_eq!(
+            parties,
+            [None, None, Some(Party::Premade), Some(Party::Solo)]
+        );
+        let game = apply_replay(&mut tally, &game(1, 0), &settings())
+            .unwrap()
+            .unwrap();
+        assert_eq!(guess_party(&tally, &game, &settings()), None);
+    }
+
+    #[test]
+    fn render_game_anonymized() {
+        let settings = Settings {
+            anonymize: true,
+            per_playlist: true,
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        let game = apply_replay(&mut tally, &game(2, 1), &settings)
+            .unwrap()
+            .unwrap();
+        let message = render_game(&tally, &game, &settings).to_markdown();
+        assert!(
+            message.contains("MVP: Player 1 (top scorer of the winning team)\n\n### Player 1\n")
+        );
+        assert!(message.contains("### Player 2\n"));
+        assert!(!message.contains("alice") && !message.contains("bob"));
+        // The tally itself keeps the real players, numbered the same in every playlist.
+        assert_eq!(tally.player_stats["alice"].name, "alice");
+        assert_eq!(
+            tally.playlists["Online 1v1"].player_stats["bob"].first_seen,
+            2
+        );
+        let anonymized = tally.anonymized();
+        assert_eq!(anonymized.player_stats["Player 2"].name, "Player 2");
+        let json = serde_json::to_string(&anonymized).unwrap();
+        assert!(!json.contains("alice") && !json.contains("bob"));
+        assert_eq!(
+            render_pseudonyms(&tally),
+            "Player 1: alice\nPlayer 2: bob\n"
+        );
+    }
+
+    #[test]
+    fn render_games_message() {
+        let mut tally = Tally::default();
+        let games: Vec<Game> = [(2, 1), (0, 3)]
+            .into_iter()
+            .map(|(alice, bob)| {
+                apply_replay(&mut tally, &game(alice, bob), &settings())
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+        let message = render_games(&tally, &games, &settings()).to_markdown();
+        assert!(message.starts_with(
+            "## 2 games finished\n- Online 1v1: Blue 2-1 Orange (nailbiter), MVP: alice\n- Online 1v1: Blue 0-3 Orange, MVP: bob\n\n### "
+        ));
+        assert!(message.contains("### alice\n*Played 2 games*\n"));
+    }
+
+    #[test]
+    fn render_summary_message() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(1, 0), (2, 0), (0, 1), (1, 1)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let summary = render_summary(&tally, &settings(), "Session complete", Local::now());
+        assert_eq!(summary.win_rate, 50);
+        let summary = summary.to_markdown();
+        assert!(summary.starts_with(
+            "## 4 games played\n### Team totals\n- Goals: 6\n- Saves: 0\n- Win rate: 50%\n*Fun fact: alice has the best shot accuracy tonight (50%)*\n\n### alice\n"
+        ));
+        assert!(summary.contains("- Wins/Losses/Draws: 2/1/1\n"));
+        assert!(summary.contains("- Goals: 4 (1), 1 per game (best: 2)\n"));
+        assert!(summary.contains("- G+A: 4 (1)\n"));
+        assert!(summary.contains("- Score: 400 (100), 100 per game, form 94 (best: 200)\n"));
+    }
+
+    #[test]
+    fn summary_fun_facts() {
+        let mut tally = Tally::default();
+        let mut facts = Vec::new();
+        for (alice, bob) in [(1, 0), (0, 2), (3, 0), (0, 1)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+            facts.push(render_fun_fact(&tally, &settings()));
+        }
+        // Nobody makes a save and the MVPs are tied after two games, so those are skipped.
+        assert_eq!(
+            facts,
+            [
+                "*Fun fact: alice has been MVP the most tonight (once)*\n",
+                "*Fun fact: bob has the best shot accuracy tonight (50%)*\n",
+                "*Fun fact: alice has been MVP the most tonight (2 times)*\n",
+                "*Fun fact: alice has the best shot accuracy tonight (50%)*\n",
+            ]
+        );
+        tally.player_stats.remove("bob");
+        assert_eq!(render_fun_fact(&tally, &settings()), "");
+    }
+
+    #[test]
+    fn render_summary_with_baseline() {
+        let mut baseline = Tally::default();
+        for (alice, bob) in [(3, 0), (1, 0)] {
+            apply_replay(&mut baseline, &game(alice, bob), &settings()).unwrap();
+        }
+        baseline.player_stats.remove("bob");
+        let settings = Settings {
+            baseline: Some(baseline),
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        for (alice, bob) in [(1, 0), (2, 0), (0, 1), (1, 1)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings).unwrap();
+        }
+        let summary = render_summary(&tally, &settings, "Session complete", Local::now());
+        let alice = &summary.players[0].stats;
+        assert!(alice.ends_with(indoc! {"
+            - Score avg: 100 (-100 vs baseline)
+            - Goals avg: 1.0 (-1.0 vs baseline)
+            - Saves avg: 0.0 (+0.0 vs baseline)
+            - Win rate: 66% (-34 vs baseline)
+        "}));
+        assert!(summary.players[1]
+            .stats
+            .ends_with("*New since the baseline*\n"));
+    }
+
+    #[test]
+    fn summary_max_players() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(1, 0), (2, 0)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let settings = |max_players| Settings {
+            max_players,
+            ..settings()
+        };
+        let summary = render_summary(&tally, &settings(Some(1)), "Session complete", Local::now());
+        assert_eq!(summary.players.len(), 1);
+        assert!(summary.players[0].heading.starts_with("alice"));
+        assert!(summary.to_markdown().ends_with("*...and 1 other*\n"));
+        assert!(summary.to_terminal(false).ends_with("\n...and 1 other\n"));
+        let summary = render_summary(&tally, &settings(None), "Session complete", Local::now());
+        assert_eq!((summary.players.len(), summary.footer), (2, None));
+        assert_eq!(tally.player_stats.len(), 2);
+    }
+
+    #[test]
+    fn summary_grouped_by_team() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(1, 2), (0, 3)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let settings = Settings {
+            group_by_team: true,
+            ..settings()
+        };
+        let summary = render_summary(&tally, &settings, "Session complete", Local::now());
+        assert!(summary
+            .header
+            .contains("*Players are listed under the team of their latest game*\n"));
+        let markdown = summary.to_markdown();
+        let blue = markdown
+            .find("## Blue\n*Goals 1, saves 0, score 100*\n### alice")
+            .unwrap();
+        let orange = markdown
+            .find("## Orange\n*Goals 5, saves 0, score 500*\n### bob")
+            .unwrap();
+        assert!(blue < orange);
+        assert_eq!(summary.grouped_players()[1].0.unwrap().name, "Orange");
+    }
+
+    #[test]
+    fn summary_almost_there() {
+        let mut tally = Tally::default();
+        for _ in 0..5 {
+            apply_replay(&mut tally, &game(1, 0), &settings()).unwrap();
+        }
+        for (id, times_seen) in [("carol", 2), ("dave", 3), ("eve", 1)] {
+            let mut stats = tally.player_stats["bob"].clone();
+            stats.name = id.to_string();
+            stats.times_seen = times_seen;
+            tally.player_stats.insert(id.to_string(), stats);
+        }
+        assert_eq!(
+            render_provisional(&tally, &settings()),
+            "*Almost there: dave (1 more game), carol (2 more games)*\n"
+        );
+        let squad = Settings {
+            presence: Presence {
+                players: vec!["alice".to_string()],
+                ..settings().presence
+            },
+            ..settings()
+        };
+        assert_eq!(render_provisional(&tally, &squad), "");
+    }
+
+    #[test]
+    fn exclude_players_within_squad() {
+        let settings = Settings {
+            presence: Presence {
+                players: vec!["alice".to_string(), "bob".to_string()],
+                ..settings().presence
+            },
+            exclude: vec!["bob".to_string()],
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        // Matching is case-insensitive, so "Bob" is left out too.
+        let mut replay = game(0, 3);
+        if let HeaderProp::Array(players) = &mut replay.properties[4].1 {
+            players[1][0].1 = HeaderProp::Str("Bob".to_string());
+        }
+        let game = apply_replay(&mut tally, &replay, &settings)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tally.games_played, 1);
+        assert!(!tally.player_stats.contains_key("Bob"));
+        let names: Vec<_> = ranked_players(&tally, &settings)
+            .iter()
+            .map(|(_, stats)| stats.name.clone())
+            .collect();
+        assert_eq!(names, ["alice"]);
+        // bob was MVP, but is not mentioned.
+        let message = render_game(&tally, &game, &settings).to_markdown();
+        assert!(!message.contains("MVP:") && !message.contains("Bob"));
+        assert_eq!(tally.history[0].team_scores, (0, 3));
+    }
+
+    #[test]
+    fn names_with_markdown_and_wide_characters() {
+        let mut replay = game(2, 1);
+        if let HeaderProp::Array(players) = &mut replay.properties[4].1 {
+            players[0][0].1 = HeaderProp::Str("# GG #".to_string());
+        }
+        let mut tally = Tally::default();
+        let game = apply_replay(&mut tally, &replay, &settings())
+            .unwrap()
+            .unwrap();
+        let report = render_game(&tally, &game, &settings());
+        assert!(report.header.contains("MVP: \\# GG #"));
+        assert!(report.to_markdown().contains("### \\# GG #\n"));
+        assert!(report.to_terminal(false).contains("\n# GG #\n"));
+
+        let lifetime = |name: &str| Lifetime {
+            name: name.to_string(),
+            games: 1,
+            wins: 1,
+            losses: 0,
+            goals: 2,
+        };
+        assert_eq!(
+            render_lifetime(&[lifetime("ロケット"), lifetime("bob")]),
+            "Lifetime stats:\n  ロケット: 1 games, 1 wins, 0 losses, 2 goals\n  bob:      1 games, 1 wins, 0 losses, 2 goals\n"
+        );
+    }
+
+    #[test]
+    fn streaks() {
+        let mut tally = Tally::default();
+        let mut streaks = Vec::new();
+        for (alice, bob) in [(1, 0), (2, 0), (1, 1), (3, 0), (0, 1), (0, 2)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+            streaks.push(tally.player_stats["alice"].streak);
+        }
+        assert_eq!(streaks, [1, 2, 2, 3, -1, -2]);
+        assert_eq!(
+            tally.player_stats["alice"].streak().as_deref(),
+            Some("❄️ L2")
+        );
+        assert_eq!(tally.player_stats["bob"].streak().as_deref(), Some("🔥 W2"));
+        let report = render_player(&tally.player_stats["bob"], &settings());
+        assert_eq!(report.heading, "bob → 🔥 W2");
+        tally.player_stats.get_mut("bob").unwrap().streak = 1;
+        assert_eq!(tally.player_stats["bob"].streak(), None);
+    }
+
+    #[test]
+    fn sort_players() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(3, 0), (0, 1), (0, 2)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let mut carol = tally.player_stats["bob"].clone();
+        carol.name = "Carol".to_string();
+        tally.player_stats.insert("carol".to_string(), carol);
+        let names = |sort_by| {
+            let settings = Settings {
+                sort_by,
+                ..settings()
+            };
+            ranked_players(&tally, &settings)
+                .iter()
+                .map(|(_, stats)| stats.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(SortBy::Score), ["alice", "bob", "Carol"]);
+        assert_eq!(names(SortBy::Goals), ["alice", "bob", "Carol"]);
+        assert_eq!(names(SortBy::Wins), ["bob", "Carol", "alice"]);
+        assert_eq!(names(SortBy::Name), ["alice", "bob", "Carol"]);
+    }
+
+    #[test]
+    fn tui_leaderboard() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(3, 0), (0, 1), (0, 2)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let mut settings = settings();
+        let rows = tui::rows(&tally, &settings);
+        assert_eq!(
+            rows[0],
+            ["alice", "3", "1/2/0", "1", "300", "100", "147", "3", "0", "0", "6"]
+        );
+        // s in the TUI goes from score to goals to wins, and back round after name.
+        settings.sort_by = settings.sort_by.next().next();
+        assert_eq!(settings.sort_by, SortBy::Wins);
+        assert_eq!(tui::rows(&tally, &settings)[0][0], "bob");
+        assert_eq!(settings.sort_by.next().next(), SortBy::Score);
+    }
+
+    #[test]
+    fn summary_pace() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(1, 0), (2, 0), (0, 1)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let now = Local::now();
+        assert_eq!(render_pace(&tally, now), "");
+        tally.started_at = Some(now - chrono::Duration::seconds(30));
+        assert_eq!(render_pace(&tally, now), "*Playing for 0h 00m*\n");
+        tally.started_at = Some(now - chrono::Duration::minutes(80));
+        let summary = render_summary(&tally, &settings(), "Session complete", now);
+        assert!(summary
+            .header
+            .starts_with("## 3 games played\n*Playing for 1h 20m, 2.2 games per hour*\n###"));
+
+        // Resuming from the state file keeps the start of the session.
+        let resumed: Tally = serde_json::from_str(&serde_json::to_string(&tally).unwrap()).unwrap();
+        assert_eq!(resumed.started_at, tally.started_at);
+    }
+
+    #[test]
+    fn team_totals_average_duration() {
+        let mut tally = Tally::default();
+        for seconds in [300.0, 361.0] {
+            let mut replay = game(1, 0);
+            replay
+                .properties
+                .push(("TotalSecondsPlayed".to_string(), HeaderProp::Float(seconds)));
+            apply_replay(&mut tally, &replay, &settings()).unwrap();
+        }
+        apply_replay(&mut tally, &game(0, 1), &settings()).unwrap();
+        assert_eq!(tally.history.len(), 3);
+        assert_eq!(tally.average_duration(), Some(330.5));
+        let totals = render_team_totals(&tally, &settings().presence, 66);
+        assert!(totals.ends_with("- Win rate: 66%\n- Average game length: 5:31\n"));
+    }
+
+    #[test]
+    fn per_game_rounds_and_handles_no_games() {
+        let mut tally = Tally::default();
+        for (alice, bob) in [(1, 0), (2, 0), (2, 1)] {
+            apply_replay(&mut tally, &game(alice, bob), &settings()).unwrap();
+        }
+        let alice = &tally.player_stats["alice"];
+        assert_eq!(alice.per_game(alice.goals.0), 2);
+        assert_eq!(alice.per_game(alice.score.0), 167);
+        let unseen = PlayerStats {
+            times_seen: 0,
+            ..alice.clone()
+        };
+        assert_eq!(unseen.per_game(unseen.score.0), 0);
+    }
+
+    #[test]
+    fn pending_replays_settle_separately() {
+        let start = Instant::now();
+        let mut pending = PendingReplays::default();
+        pending.created(PathBuf::from("a.replay"), start);
+        pending.created(
+            PathBuf::from("b.replay"),
+            start + Duration::from_millis(500),
+        );
+        pending.modified(Path::new("a.replay"), start + Duration::from_millis(1000));
+        pending.modified(Path::new("unknown.replay"), start);
+        assert_eq!(
+            pending.next_deadline(),
+            Some(start + Duration::from_millis(500) + SETTLE_TIME)
+        );
+
+        assert!(pending.take_settled(start + SETTLE_TIME).is_empty());
+        let later = start + Duration::from_millis(1000) + SETTLE_TIME;
+        assert_eq!(
+            pending.take_settled(later),
+            vec![PathBuf::from("b.replay"), PathBuf::from("a.replay")]
+        );
+        assert_eq!(pending.next_deadline(), None);
+    }
+
+    #[test]
+    fn pending_replays_drop_removed() {
+        let start = Instant::now();
+        let mut pending = PendingReplays::default();
+        pending.created(PathBuf::from("a.replay"), start);
+        assert!(pending.removed(Path::new("a.replay")));
+        assert!(!pending.removed(Path::new("a.replay")));
+        assert!(pending.take_settled(start + SETTLE_TIME).is_empty());
+    }
+
+    #[test]
+    fn pending_replays_cool_down_after_reading() {
+        let start = Instant::now();
+        let cooldown = Duration::from_secs(5);
+        let mut pending = PendingReplays::new(cooldown);
+        pending.created(PathBuf::from("a.replay"), start);
+        let read = start + SETTLE_TIME;
+        assert_eq!(pending.take_settled(read), vec![PathBuf::from("a.replay")]);
+
+        // Written again right away, as happens on a re-export.
+        pending.created(PathBuf::from("a.replay"), read + Duration::from_secs(1));
+        pending.modified(Path::new("a.replay"), read + Duration::from_secs(1));
+        assert_eq!(pending.next_deadline(), None);
+
+        pending.created(PathBuf::from("a.replay"), read + cooldown);
+        assert_eq!(
+            pending.take_settled(read + cooldown + SETTLE_TIME),
+            vec![PathBuf::from("a.replay")]
+        );
+    }
+
+    #[test]
+    fn pending_replays_retry_a_few_times() {
+        let start = Instant::now();
+        let mut pending = PendingReplays::default();
+        pending.created(PathBuf::from("a.replay"), start);
+        let mut now = start + SETTLE_TIME;
+        assert_eq!(pending.take_settled(now), vec![PathBuf::from("a.replay")]);
+        for _ in 0..MAX_READ_RETRIES {
+            assert!(pending.retry(PathBuf::from("a.replay"), now));
+            assert_eq!(pending.next_deadline(), Some(now + SETTLE_TIME));
+            now += SETTLE_TIME;
+            assert_eq!(pending.take_settled(now), vec![PathBuf::from("a.replay")]);
+        }
+        assert!(!pending.retry(PathBuf::from("a.replay"), now));
+        assert_eq!(pending.next_deadline(), None);
+    }
+
+    #[test]
+    fn aliases_fold_into_one_player() {
+        let settings = Settings {
+            aliases: Aliases::new(HashMap::from([(
+                "Alice".to_string(),
+                vec!["alice".to_string(), "alice_alt".to_string()],
+            )]))
+            .unwrap(),
+            ..settings()
+        };
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(1, 0), &settings).unwrap();
+        let mut alt = game(2, 0);
+        if let HeaderProp::Array(players) = &mut alt.properties[4].1 {
+            players[0][0].1 = HeaderProp::Str("alice_alt".to_string());
+        }
+        apply_replay(&mut tally, &alt, &settings).unwrap();
+
+        assert_eq!(tally.player_stats.len(), 2);
+        let alice = &tally.player_stats["Alice"];
+        assert_eq!(alice.name, "Alice");
+        assert_eq!((alice.times_seen, alice.goals.0), (2, 3));
+        assert_eq!(tally.player_stats["bob"].times_seen, 2);
+    }
+
+    #[test]
+    fn render_player_with_template() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(2, 1), &settings()).unwrap();
+        let settings = Settings {
+            template: Template::parse(
+                "{name}: {wins}W {score_tally} pts, {differential}\n",
+                PLAYER_PLACEHOLDERS,
+            )
+            .unwrap(),
+            ..settings()
+        };
+        let player = render_player(&tally.player_stats["alice"], &settings);
+        assert_eq!(player.stats, "alice: 1W 200 pts, +1\n");
+        assert_eq!(player.win_rate, Some(100));
+        assert!(Template::parse("{goals_per_minute}", PLAYER_PLACEHOLDERS).is_err());
+    }
+
+    #[test]
+    fn render_player_columns() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(2, 1), &settings()).unwrap();
+        let columns = [Column::Goals, Column::Wins];
+        let settings = Settings {
+            template: Template::parse(&columns_template(&columns), PLAYER_PLACEHOLDERS).unwrap(),
+            ..settings()
+        };
+        let player = render_player(&tally.player_stats["alice"], &settings);
+        assert_eq!(
+            player.stats,
+            "- Goals: 2 (2), 2 per game (best: 2)\n- Wins/Losses/Draws: 1/0/0\n"
+        );
+        assert_eq!(columns_template(&[]).lines().count(), 12);
+        assert!(Column::from_str("shots", true).is_ok());
+        assert!(Column::from_str("demos", true).is_err());
+    }
+
+    #[test]
+    fn render_player_boost_efficiency() {
+        let mut tally = Tally::default();
+        apply_replay(&mut tally, &game(2, 1), &settings()).unwrap();
+        let alice = tally.player_stats.get_mut("alice").unwrap();
+        assert!(!render_player(alice, &settings()).stats.contains("Demos"));
+        let settings = Settings {
+            deep_parse: true,
+            ..settings()
+        };
+        assert!(render_player(alice, &settings).stats.ends_with(
+            "- Demos, boost and touches: not available, the network data could not be read\n"
+        ));
+
+        let deep = DeepStats {
+            boost_collected: 450,
+            boost_used: 400,
+            own_goals: 1,
+            demos_inflicted: 3,
+            demos_taken: 1,
+            ..Default::default()
+        };
+        alice.deep = Some((deep, deep));
+        let stats = render_player(alice, &settings).stats;
+        assert!(stats.contains("- Boost collected/used: 450/400 (450/400)\n"));
+        assert!(stats.contains("- Own goals: 1 (1)\n"));
+        assert!(stats.contains("- Demos: 3 given / 1 taken (3/1)\n"));
+        assert!(stats.ends_with("- Score per 100 boost used: 50\n"));
+    }
+
+    #[test]
+    fn backfill_replays_in_subfolders() {
+        let dir = std::env::temp_dir().join(format!("rl-session-backfill-{}", std::process::id()));
+        let sub = dir.join("2023-01-02");
+        fs::create_dir_all(&sub).unwrap();
+        for file in [
+            dir.join("a.replay"),
+            sub.join("b.replay"),
+            sub.join("notes.txt"),
+        ] {
+            fs::write(file, "").unwrap();
+        }
+        let window = Duration::from_secs(3600);
+
+        let flat = backfill_replays(std::slice::from_ref(&dir), window, false, None);
+        assert_eq!(flat, vec![dir.join("a.replay")]);
+        let mut nested = backfill_replays(std::slice::from_ref(&dir), window, true, None);
+        nested.sort();
+        assert_eq!(
+            nested,
+            vec![dir.join("2023-01-02/b.replay"), dir.join("a.replay")]
+        );
+        let glob = parse_glob("B*").unwrap();
+        assert_eq!(
+            backfill_replays(std::slice::from_ref(&dir), window, true, Some(&glob)),
+            vec![dir.join("2023-01-02/b.replay")]
+        );
+        assert!(!wanted_replay(&dir.join("b.txt"), Some(&glob)));
+        assert!(parse_glob("[").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn since_date_or_duration() {
+        let now = Local::now();
+        assert_eq!(
+            parse_since_at("2h", now),
+            Ok(now - chrono::Duration::hours(2))
+        );
+        let date = parse_since_at("2023-01-02T15:04:05+01:00", now).unwrap();
+        assert_eq!(date.timestamp(), 1672668245);
+        assert!(parse_since_at("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn image_urls() {
+        let url = "https://example.com/logo.png";
+        assert_eq!(parse_image_url(url).as_deref(), Ok(url));
+        assert!(parse_image_url("http://example.com/logo.png").is_ok());
+        assert!(parse_image_url("ftp://example.com/logo.png").is_err());
+        assert!(parse_image_url("logo.png").is_err());
+        assert!(parse_image_url("file:///home/me/logo.png").is_err());
+    }
+
+    #[test]
+    fn played_before_since() {
+        let mut tally = Tally::default();
+        let mut game = apply_replay(&mut tally, &game(1, 0), &settings())
+            .unwrap()
+            .unwrap();
+        let now = Local::now();
+        assert!(!played_before(&game, Some(now)));
+        game.played_at = Some(now - chrono::Duration::hours(3));
+        assert!(played_before(&game, Some(now - chrono::Duration::hours(2))));
+        assert!(!played_before(
+            &game,
+            Some(now - chrono::Duration::hours(4))
+        ));
+        assert!(!played_before(&game, None));
+    }
+
+    #[test]
+    fn outcome_win_and_loss() {
+        assert_eq!(outcome(0, 3, 1, false), Some(Outcome::Win));
+        assert_eq!(outcome(1, 3, 1, false), Some(Outcome::Loss));
+        assert_eq!(outcome(0, 0, 2, false), Some(Outcome::Loss));
+        assert_eq!(outcome(1, 0, 2, false), Some(Outcome::Win));
+    }
+
+    #[test]
+    fn outcome_tie() {
+        assert_eq!(outcome(0, 2, 2, false), Some(Outcome::Draw));
+        assert_eq!(outcome(1, 2, 2, false), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn outcome_forfeit() {
+        assert_eq!(outcome(0, 1, 0, true), Some(Outcome::Win));
+        assert_eq!(outcome(1, 1, 0, true), Some(Outcome::Loss));
+        assert_eq!(outcome(0, 1, 1, true), None);
+    }
+
+    #[test]
+    fn outcome_malformed_team() {
+        assert_eq!(outcome(2, 3, 1, false), None);
+        assert_eq!(outcome(2, 2, 2, false), None);
+        assert_eq!(outcome(usize::MAX, 0, 1, false), None);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{tracked_players, PlayerStats, Settings, Tally};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Messages that could not be sent after all attempts, for `rl_webhook_failures_total`.
+pub static WEBHOOK_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Reads a stat of a player.
+type Stat = fn(&PlayerStats) -> usize;
+
+/// Stats of the tracked players, as `rl_player_<stat>{id="...",player="..."}`. Names can be
+/// shared, so the key the player is kept under in the tally tells the series apart. With
+/// `--anonymize` they are only labelled with their pseudonym, which is unique.
+const PLAYER_METRICS: &[(&str, &str, Stat)] = &[
+    ("games", "Games the player was seen in.", |s| s.times_seen),
+    ("wins", "Games the player won.", |s| s.wins),
+    ("losses", "Games the player lost.", |s| s.losses),
+    ("mvps", "Games the player was MVP in.", |s| s.mvps),
+    ("score", "Score of the player.", |s| s.score.0),
+    ("goals", "Goals of the player.", |s| s.goals.0),
+    ("assists", "Assists of the player.", |s| s.assists.0),
+    ("saves", "Saves of the player.", |s| s.saves.0),
+    ("shots", "Shots of the player.", |s| s.shots.0),
+];
+
+/// The tally in the Prometheus text format, for GET /metrics. Everything is about the
+/// current session, and only players the session tracks get series, so opponents met once do
+/// not pile up.
+pub fn render(tally: &Tally, settings: &Settings) -> String {
+    let mut text = metric(
+        "rl_games_total",
+        "counter",
+        "Games played.",
+        &[(String::new(), tally.games_played as u64)],
+    );
+    text.push_str(&metric(
+        "rl_webhook_failures_total",
+        "counter",
+        "Messages that could not be sent, after retrying.",
+        &[(String::new(), WEBHOOK_FAILURES.load(Ordering::Relaxed))],
+    ));
+    // With `--anonymize` the pseudonym is the only label, as the key is the real identity.
+    let mut players: Vec<(String, &PlayerStats)> = tracked_players(tally, &settings.presence)
+        .into_iter()
+        .map(|(id, stats)| {
+            let labels = if settings.anonymize {
+                format!("{{player=\"{}\"}}", stats.pseudonym())
+            } else {
+                format!(
+                    "{{id=\"{}\",player=\"{}\"}}",
+                    escape(id),
+                    escape(&stats.name)
+                )
+            };
+            (labels, stats)
+        })
+        .collect();
+    players.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    for (stat, help, value) in PLAYER_METRICS {
+        let samples: Vec<(String, u64)> = players
+            .iter()
+            .map(|(labels, stats)| (labels.clone(), value(stats) as u64))
+            .collect();
+        text.push_str(&metric(
+            &format!("rl_player_{stat}"),
+            "gauge",
+            help,
+            &samples,
+        ));
+    }
+    text
+}
+
+/// A metric with its help and type lines, and a line per set of labels.
+fn metric(name: &str, kind: &str, help: &str, samples: &[(String, u64)]) -> String {
+    let mut text = format!("# HELP {name} {help}\n# TYPE {name} {kind}\n");
+    for (labels, value) in samples {
+        text.push_str(&format!("{name}{labels} {value}\n"));
+    }
+    text
+}
+
+/// Label values are quoted, so backslashes, quotes and line breaks in names need escaping.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+        assert_eq!(escape("two\nlines"), "two\\nlines");
+    }
+}
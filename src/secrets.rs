@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Credentials kept in their own file, see `--secrets`. They win over the command line, the
+/// environment and the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Secrets {
+    #[serde(default, deserialize_with = "crate::config::one_or_many")]
+    pub webhook: Vec<String>,
+    pub telegram_token: Option<String>,
+}
+
+impl Secrets {
+    /// Loads the secrets at `path`, TOML unless it ends in `.json`. On Unix, a file that others
+    /// than its owner can read or write is refused.
+    pub fn load(path: &Path) -> Result<Self> {
+        let shown = path.to_string_lossy();
+        let metadata =
+            fs::metadata(path).with_context(|| format!("Could not read secrets file {shown}"))?;
+        check_permissions(&metadata).with_context(|| format!("Refusing secrets file {shown}"))?;
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Could not read secrets file {shown}"))?;
+        let json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let secrets = if json {
+            serde_json::from_str(&data).map_err(|e| anyhow!(e))
+        } else {
+            toml::from_str(&data).map_err(|e| anyhow!(e))
+        };
+        secrets.with_context(|| format!("Secrets file {shown} is not valid"))
+    }
+}
+
+#[cfg(unix)]
+fn check_permissions(metadata: &fs::Metadata) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(anyhow!(
+            "it can be read or changed by other users (mode {:o}), restrict it with chmod 600",
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+/// Windows has no permission bits to go by, the file is trusted as it is.
+#[cfg(not(unix))]
+fn check_permissions(_metadata: &fs::Metadata) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn secrets_file_permissions() {
+        let path =
+            std::env::temp_dir().join(format!("rl-session-secrets-{}.toml", std::process::id()));
+        fs::write(&path, "webhook = \"https://example.com/hook\"\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(Secrets::load(&path)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Refusing secrets file"));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let secrets = Secrets::load(&path).unwrap();
+        assert_eq!(secrets.webhook, ["https://example.com/hook"]);
+        assert_eq!(secrets.telegram_token, None);
+
+        fs::write(
+            &path,
+            "webhook = [\"https://example.com/a\", \"https://example.com/b\"]\n",
+        )
+        .unwrap();
+        let secrets = Secrets::load(&path).unwrap();
+        assert_eq!(
+            secrets.webhook,
+            ["https://example.com/a", "https://example.com/b"]
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
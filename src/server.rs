@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Result};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+
+use crate::{metrics, Settings, Tally};
+
+/// A page that shows the live tally as a table, refreshing itself. Built in, so there is
+/// nothing to ship next to the binary.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Serves the live tally as JSON on GET /stats, for overlays, a page showing it on GET /, and
+/// Prometheus metrics on GET /metrics. POST /reset calls `reset`, to start a new session.
+/// The server runs on its own thread and reads whatever the main loop last put in `tally`.
+pub fn serve(
+    addr: &str,
+    tally: Arc<Mutex<Tally>>,
+    settings: Settings,
+    reset: impl Fn() + Send + 'static,
+) -> Result<()> {
+    let server =
+        Server::http(addr).map_err(|e| anyhow!("Could not serve stats on {}: {}", addr, e))?;
+    info!(
+        "Serving stats on http://{}/stats and http://{}/, metrics on http://{}/metrics",
+        addr, addr, addr
+    );
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            if let Err(e) = respond(request, &tally, &settings, &reset) {
+                warn!("Failed to answer stats request: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn respond(
+    request: Request,
+    tally: &Mutex<Tally>,
+    settings: &Settings,
+    reset: &dyn Fn(),
+) -> Result<()> {
+    match (request.method(), request.url()) {
+        (Method::Get, "/stats") => {}
+        (Method::Post, "/reset") => {
+            reset();
+            request
+                .respond(Response::from_string("Starting a new session").with_status_code(202))?;
+            return Ok(());
+        }
+        (Method::Get, "/metrics") => {
+            let text = {
+                let tally = tally
+                    .lock()
+                    .map_err(|_| anyhow!("Tally lock is poisoned"))?;
+                metrics::render(&tally, settings)
+            };
+            let response = Response::from_string(text)
+                .with_header(header("Content-Type", "text/plain; version=0.0.4"));
+            request.respond(response)?;
+            return Ok(());
+        }
+        (Method::Get, "/") => {
+            let response = Response::from_string(DASHBOARD)
+                .with_header(header("Content-Type", "text/html; charset=utf-8"));
+            request.respond(response)?;
+            return Ok(());
+        }
+        _ => {
+            request.respond(Response::from_string("Not found").with_status_code(404))?;
+            return Ok(());
+        }
+    }
+
+    let json = {
+        let tally = tally
+            .lock()
+            .map_err(|_| anyhow!("Tally lock is poisoned"))?;
+        if settings.anonymize {
+            serde_json::to_string(&tally.anonymized())?
+        } else {
+            serde_json::to_string(&*tally)?
+        }
+    };
+    let response = Response::from_string(json)
+        .with_header(header("Content-Type", "application/json"))
+        // Overlays are usually local html files, which need this to fetch from us.
+        .with_header(header("Access-Control-Allow-Origin", "*"));
+    request.respond(response)?;
+    Ok(())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header is valid ascii")
+}
@@ -0,0 +1,464 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use owo_colors::OwoColorize;
+use std::{future::Future, sync::atomic::Ordering, time::Duration};
+use tracing::warn;
+
+use crate::metrics;
+
+/// How long to wait before the first retry, doubled after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Which service the stats get posted to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SinkKind {
+    #[default]
+    Discord,
+    Slack,
+    Telegram,
+    /// Any webhook taking JSON, see `--generic-webhook`.
+    Generic,
+}
+
+/// The stats after a game or for the session, split up so sinks can lay them out as they like.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub title: Option<String>,
+    /// Markdown above the players, e.g. the game number and MVP.
+    pub header: String,
+    pub players: Vec<PlayerReport>,
+    /// Plain text below the players, e.g. how many were left out.
+    pub footer: Option<String>,
+    /// Win rate of the tracked players in percent.
+    pub win_rate: usize,
+}
+
+/// A player's part of a [`Report`].
+#[derive(Debug, Clone, Default)]
+pub struct PlayerReport {
+    /// The player's name, and whatever goes next to it.
+    pub heading: String,
+    /// Markdown stats.
+    pub stats: String,
+    /// Nothing until the player has won or lost a game.
+    pub win_rate: Option<usize>,
+    /// What the player is listed under. Players of a group follow each other.
+    pub group: Option<Group>,
+}
+
+/// A heading players are listed under, e.g. their team with `--group-by-team`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    /// Markdown line summing up the players in the group.
+    pub subtotal: String,
+}
+
+impl Report {
+    /// Every player, with the group that starts at them, if any.
+    pub fn grouped_players(&self) -> Vec<(Option<&Group>, &PlayerReport)> {
+        let mut previous = None;
+        self.players
+            .iter()
+            .map(|player| {
+                let group = player.group.as_ref();
+                let starts = group.is_some() && group != previous;
+                previous = group;
+                (group.filter(|_| starts), player)
+            })
+            .collect()
+    }
+
+    /// The report as one markdown text, without the title.
+    pub fn to_markdown(&self) -> String {
+        let mut text = self.header.clone();
+        for (group, player) in self.grouped_players() {
+            if let Some(group) = group {
+                text.push_str(&group.to_markdown());
+            }
+            text.push_str(&format!("### {}\n{}", player.heading, player.stats));
+        }
+        if let Some(footer) = &self.footer {
+            text.push_str(&format!("*{}*\n", footer));
+        }
+        text
+    }
+
+    /// The report for reading in a terminal, with the title. Markdown headings and emphasis
+    /// become bold and italic text when `color` is set, and are left out otherwise. Player
+    /// names are green when they win at least half their games and red otherwise.
+    pub fn to_terminal(&self, color: bool) -> String {
+        let mut text = String::new();
+        if let Some(title) = &self.title {
+            let title = styled(title, color, |text| text.bold().underline().to_string());
+            text.push_str(&format!("{}\n\n", title));
+        }
+        text.push_str(&terminal_lines(&self.header, color));
+        for (group, player) in self.grouped_players() {
+            if let Some(group) = group {
+                text.push_str(&terminal_lines(&group.to_markdown(), color));
+            }
+            let heading = unescape_markdown(&player.heading);
+            let heading = match (color, player.win_rate) {
+                (false, _) => heading,
+                (true, None) => heading.bold().to_string(),
+                (true, Some(rate)) if rate >= 50 => heading.bold().green().to_string(),
+                (true, Some(_)) => heading.bold().red().to_string(),
+            };
+            text.push_str(&format!(
+                "{}\n{}",
+                heading,
+                terminal_lines(&player.stats, color)
+            ));
+        }
+        if let Some(footer) = &self.footer {
+            text.push_str(&styled(footer, color, |text| text.italic().to_string()));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Group {
+    fn to_markdown(&self) -> String {
+        format!("## {}\n{}\n", self.name, self.subtotal)
+    }
+}
+
+fn styled(text: &str, color: bool, style: impl Fn(&str) -> String) -> String {
+    if color {
+        style(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Turns markdown headings and lines in `*emphasis*` into plain text, styled if `color` is set.
+fn terminal_lines(markdown: &str, color: bool) -> String {
+    markdown
+        .split_inclusive('\n')
+        .map(|line| {
+            let (content, end) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            let heading = content.trim_start_matches('#');
+            let emphasis = content
+                .strip_prefix('*')
+                .and_then(|content| content.strip_suffix('*'))
+                .filter(|content| !content.is_empty());
+            let content = if heading.len() < content.len() {
+                styled(&unescape_markdown(heading.trim()), color, |text| {
+                    text.bold().to_string()
+                })
+            } else if let Some(emphasis) = emphasis {
+                styled(&unescape_markdown(emphasis), color, |text| {
+                    text.italic().to_string()
+                })
+            } else {
+                unescape_markdown(content)
+            };
+            content + end
+        })
+        .collect()
+}
+
+/// Characters that format markdown wherever they are.
+const MARKDOWN_CHARS: &[char] = &['\\', '*', '_', '~', '`', '|', '[', ']', '>'];
+
+/// Escapes text from players, i.e. their names, so it shows as it is in markdown. A name
+/// like "# GG #" at the start of a line would otherwise become a heading.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        if MARKDOWN_CHARS.contains(&c) || (i == 0 && matches!(c, '#' | '-' | '+')) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Undoes [`escape_markdown`] for the terminal, where markdown is not rendered.
+fn unescape_markdown(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && next.is_ascii_punctuation() => {}
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Somewhere to post the stats. The text is markdown.
+#[async_trait]
+pub trait StatsSink: Send + Sync {
+    /// Posts a message, optionally with a title above it.
+    async fn send_message(&self, title: Option<&str>, text: &str) -> Result<()>;
+
+    /// Posts the stats after a game or for the session.
+    async fn send_report(&self, report: &Report) -> Result<()> {
+        self.send_message(report.title.as_deref(), &report.to_markdown())
+            .await
+    }
+
+    /// Posts a PNG image, e.g. a chart of the session. Only some services take files.
+    async fn send_image(&self, title: &str, _png: &[u8]) -> Result<()> {
+        Err(anyhow!("Cannot post images like \"{}\" here", title))
+    }
+}
+
+/// Several sinks that get every message, e.g. a webhook per channel. Every sink retries on its
+/// own, and one failing does not keep the message from the others.
+pub struct Broadcast {
+    sinks: Vec<Box<dyn StatsSink>>,
+}
+
+impl Broadcast {
+    pub fn new(sinks: Vec<Box<dyn StatsSink>>) -> Self {
+        Self { sinks }
+    }
+
+    /// Fails if sending failed for any of the sinks, saying which.
+    fn result(&self, results: Vec<Result<()>>) -> Result<()> {
+        let errors: Vec<String> = results
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, result)| result.err().map(|e| format!("#{}: {}", i + 1, e)))
+            .collect();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Failed for {} of {} webhooks: {}",
+            errors.len(),
+            self.sinks.len(),
+            errors.join("; ")
+        ))
+    }
+}
+
+#[async_trait]
+impl StatsSink for Broadcast {
+    async fn send_message(&self, title: Option<&str>, text: &str) -> Result<()> {
+        let mut results = Vec::new();
+        for sink in &self.sinks {
+            results.push(sink.send_message(title, text).await);
+        }
+        self.result(results)
+    }
+
+    async fn send_report(&self, report: &Report) -> Result<()> {
+        let mut results = Vec::new();
+        for sink in &self.sinks {
+            results.push(sink.send_report(report).await);
+        }
+        self.result(results)
+    }
+
+    async fn send_image(&self, title: &str, png: &[u8]) -> Result<()> {
+        let mut results = Vec::new();
+        for sink in &self.sinks {
+            results.push(sink.send_image(title, png).await);
+        }
+        self.result(results)
+    }
+}
+
+/// Runs `send` until it succeeds, up to `attempts` times, with exponential backoff in between.
+/// When the error is a rate limit response with a `retry_after`, we wait as long as it asks instead.
+pub async fn with_retry<F, Fut>(attempts: usize, mut send: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let attempts = attempts.max(1);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let error = match send().await {
+            Ok(_) => return Ok(()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= attempts {
+            metrics::WEBHOOK_FAILURES.fetch_add(1, Ordering::Relaxed);
+            return Err(anyhow!("Giving up after {} attempts: {}", attempts, error));
+        }
+
+        let wait = retry_after(&error).unwrap_or(backoff);
+        warn!(
+            "Sending stats failed (attempt {}/{}), retrying in {:.1}s: {}",
+            attempt,
+            attempts,
+            wait.as_secs_f64(),
+            error
+        );
+        tokio::time::sleep(wait).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Failed requests hand us the response body (possibly after the status), which for a
+/// Discord 429 is a JSON object with the number of seconds to wait in `retry_after`.
+/// Telegram puts it in `parameters.retry_after` instead.
+fn retry_after(error: &str) -> Option<Duration> {
+    let body: serde_json::Value = serde_json::from_str(&error[error.find('{')?..]).ok()?;
+    let seconds = body
+        .get("retry_after")
+        .or_else(|| body.get("parameters")?.get("retry_after"))?
+        .as_f64()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Splits `text` into pieces of at most `limit` characters for services that cap the message
+/// length. Pieces are cut between lines where possible, only a single line that is too long
+/// on its own gets cut in the middle.
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if chunk_len + line_len > limit && !chunk.is_empty() {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = 0;
+        }
+        if line_len > limit {
+            let chars: Vec<char> = line.chars().collect();
+            let mut pieces = chars.chunks(limit).map(String::from_iter).peekable();
+            while let Some(piece) = pieces.next() {
+                if pieces.peek().is_some() {
+                    chunks.push(piece);
+                } else {
+                    chunk_len = piece.chars().count();
+                    chunk = piece;
+                }
+            }
+            continue;
+        }
+        chunk.push_str(line);
+        chunk_len += line_len;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn split_message_between_lines() {
+        let text = "aaaa\nbbbb\ncc\n";
+        assert_eq!(split_message(text, 100), vec![text.to_string()]);
+        assert_eq!(split_message(text, 10), vec!["aaaa\nbbbb\n", "cc\n"]);
+        assert_eq!(split_message(text, 6), vec!["aaaa\n", "bbbb\n", "cc\n"]);
+    }
+
+    #[test]
+    fn split_message_long_line() {
+        assert_eq!(
+            split_message("ab\nééééééé\nc", 3),
+            vec!["ab\n", "ééé", "ééé", "é\nc"]
+        );
+        assert!(split_message("", 10).is_empty());
+    }
+
+    fn report() -> Report {
+        Report {
+            title: Some("Session complete".to_string()),
+            header: "## 2 games played\n*Win rate: 50%*\n\n".to_string(),
+            players: vec![PlayerReport {
+                heading: "alice".to_string(),
+                stats: "*Played 2 games*\n- Goals: 3 (1)\n".to_string(),
+                win_rate: Some(50),
+                group: None,
+            }],
+            footer: None,
+            win_rate: 50,
+        }
+    }
+
+    #[test]
+    fn terminal_without_color() {
+        assert_eq!(
+            report().to_terminal(false),
+            "Session complete\n\n2 games played\nWin rate: 50%\n\nalice\nPlayed 2 games\n- Goals: 3 (1)\n"
+        );
+    }
+
+    #[test]
+    fn terminal_with_color() {
+        let text = report().to_terminal(true);
+        assert!(text.contains(&"alice".bold().green().to_string()));
+        assert!(text.contains(&"2 games played".bold().to_string()));
+        assert!(!text.contains('#'));
+    }
+
+    /// Adds what it is sent to a shared log, or fails every time.
+    struct Recorder {
+        fails: bool,
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl StatsSink for Recorder {
+        async fn send_message(&self, _title: Option<&str>, text: &str) -> Result<()> {
+            if self.fails {
+                return Err(anyhow!("unreachable"));
+            }
+            self.sent.lock().unwrap().push(text.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcast_past_failures() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sinks: Vec<Box<dyn StatsSink>> = [false, true, false]
+            .into_iter()
+            .map(|fails| {
+                Box::new(Recorder {
+                    fails,
+                    sent: sent.clone(),
+                }) as _
+            })
+            .collect();
+        let error = Broadcast::new(sinks)
+            .send_message(None, "hi")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed for 1 of 3 webhooks: #2: unreachable"
+        );
+        assert_eq!(*sent.lock().unwrap(), vec!["hi", "hi"]);
+    }
+
+    #[test]
+    fn escape_player_names() {
+        assert_eq!(escape_markdown("# GG #"), "\\# GG #");
+        assert_eq!(escape_markdown("*_x_*"), "\\*\\_x\\_\\*");
+        assert_eq!(escape_markdown("🚀 ロケット"), "🚀 ロケット");
+        for name in ["# GG #", "a\\b", "*_x_*", "- dash"] {
+            assert_eq!(unescape_markdown(&escape_markdown(name)), name);
+        }
+    }
+
+    #[test]
+    fn retry_after_telegram() {
+        let error = r#"429 Too Many Requests {"ok":false,"parameters":{"retry_after":3}}"#;
+        assert_eq!(retry_after(error), Some(Duration::from_secs(3)));
+        assert_eq!(
+            retry_after(r#"{"retry_after":0.5}"#),
+            Some(Duration::from_millis(500))
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::{
+    http::JsonClient,
+    sink::{with_retry, StatsSink},
+};
+
+/// A Slack incoming webhook.
+pub struct Slack {
+    client: JsonClient,
+    url: String,
+    attempts: usize,
+}
+
+impl Slack {
+    pub fn new(url: &str, attempts: usize, client: JsonClient) -> Self {
+        Self {
+            client,
+            url: url.to_string(),
+            attempts,
+        }
+    }
+}
+
+#[async_trait]
+impl StatsSink for Slack {
+    async fn send_message(&self, title: Option<&str>, text: &str) -> Result<()> {
+        let text = match title {
+            Some(title) => format!("*{}*\n{}", title, text),
+            None => text.to_string(),
+        };
+        let payload = json!({ "text": text });
+        with_retry(self.attempts, || async {
+            self.client.post(&self.url, &payload).await.map(|_| ())
+        })
+        .await
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::{
+    http::JsonClient,
+    sink::{split_message, with_retry, StatsSink},
+};
+
+/// Telegram refuses messages longer than this.
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// A Telegram bot posting to a single chat through the Bot API.
+pub struct Telegram {
+    client: JsonClient,
+    url: String,
+    chat: String,
+    attempts: usize,
+}
+
+impl Telegram {
+    pub fn new(token: &str, chat: &str, attempts: usize, client: JsonClient) -> Self {
+        Self {
+            client,
+            url: format!("https://api.telegram.org/bot{}/sendMessage", token),
+            chat: chat.to_string(),
+            attempts,
+        }
+    }
+}
+
+#[async_trait]
+impl StatsSink for Telegram {
+    /// Long messages are sent as several messages, in order.
+    async fn send_message(&self, title: Option<&str>, text: &str) -> Result<()> {
+        for chunk in split_message(&to_message(title, text), MAX_MESSAGE_LEN) {
+            let payload = json!({
+                "chat_id": self.chat,
+                "text": chunk,
+                "parse_mode": "MarkdownV2",
+            });
+            with_retry(self.attempts, || async {
+                self.client.post(&self.url, &payload).await.map(|_| ())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Characters MarkdownV2 refuses unless escaped, wherever they are.
+const MARKDOWN_V2_CHARS: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+/// The message in Telegram's MarkdownV2, with the title in bold.
+fn to_message(title: Option<&str>, text: &str) -> String {
+    match title {
+        Some(title) => format!("*{}*\n{}", escape(title), to_telegram_markdown(text)),
+        None => to_telegram_markdown(text),
+    }
+}
+
+/// Telegram's Markdown has no headings, so they are made bold instead. Emphasis becomes
+/// italic, as a single `*` is bold there, and everything else is escaped the way MarkdownV2
+/// wants, including what [`escape_markdown`](crate::sink::escape_markdown) escaped already.
+fn to_telegram_markdown(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let (content, end) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            let heading = content.trim_start_matches('#');
+            if heading.len() < content.len() && heading.starts_with(' ') {
+                format!("*{}*{}", inline(heading.trim()), end)
+            } else {
+                format!("{}{}", inline(content), end)
+            }
+        })
+        .collect()
+}
+
+/// A line of markdown in MarkdownV2.
+fn inline(markdown: &str) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    let mut chars = markdown.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(next)) if next.is_ascii_punctuation() => {
+                text.push_str(&escape(&next.to_string()));
+                chars.next();
+            }
+            ('*', _) => text.push('_'),
+            _ => text.push_str(&escape(&c.to_string())),
+        }
+    }
+    text
+}
+
+/// Plain text in MarkdownV2.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MARKDOWN_V2_CHARS.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::escape_markdown;
+
+    #[test]
+    fn markdown_v2_message() {
+        let text = format!(
+            "### {}\n- Goals: 3 (1)\n*Played 2024-01-01*\n",
+            escape_markdown("#1 > *you*")
+        );
+        assert_eq!(
+            to_message(Some("Session complete!"), &text),
+            "*Session complete\\!*\n*\\#1 \\> \\*you\\**\n\\- Goals: 3 \\(1\\)\n_Played 2024\\-01\\-01_\n"
+        );
+    }
+}
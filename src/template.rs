@@ -0,0 +1,92 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// A text with `{placeholder}`s, like a `format!` string. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Placeholder(String),
+}
+
+impl Template {
+    /// Parses `text`, failing if it uses a placeholder that is not in `known`.
+    pub fn parse(text: &str, known: &[&str]) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(anyhow!("Unclosed {{ in template")),
+                        }
+                    }
+                    let name = name.trim();
+                    if !known.contains(&name) {
+                        return Err(anyhow!(
+                            "Unknown placeholder {{{}}} in template, expected one of: {}",
+                            name,
+                            known.join(", ")
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Placeholder(name.to_string()));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        Ok(Self { pieces })
+    }
+
+    /// Fills in the placeholders. Ones without a value are left empty.
+    pub fn render(&self, values: &HashMap<&str, String>) -> String {
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.as_str(),
+                Piece::Placeholder(name) => values.get(name.as_str()).map_or("", String::as_str),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_placeholders() {
+        let template = Template::parse("{name} has {{{ wins }}} wins", &["name", "wins"]).unwrap();
+        let values = HashMap::from([("name", "alice".to_string()), ("wins", "3".to_string())]);
+        assert_eq!(template.render(&values), "alice has {3} wins");
+    }
+
+    #[test]
+    fn unknown_placeholder() {
+        let error = Template::parse("{name} {losses}", &["name"]).unwrap_err();
+        assert!(error.to_string().contains("{losses}"));
+        assert!(Template::parse("{name", &["name"]).is_err());
+    }
+}
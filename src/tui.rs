@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::Style,
+    widgets::{Block, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{ranked_players, win_rate, Settings, Tally};
+
+/// How long to wait for a key before drawing the leaderboard again.
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// Columns of the leaderboard, see [`rows`].
+const COLUMNS: [&str; 11] = [
+    "Player", "Games", "W/L/D", "MVPs", "Score", "Avg", "Form", "Goals", "Assists", "Saves",
+    "Shots",
+];
+
+/// A live leaderboard that takes over the terminal, see `--tui`. Like the stats server it runs
+/// on its own thread and draws whatever the main loop last put in the tally.
+pub struct Tui {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl Tui {
+    /// Starts drawing the tally. Pressing q calls `quit`, which should wrap up the session,
+    /// and s switches to the next sort order.
+    pub fn start(
+        tally: Arc<Mutex<Tally>>,
+        mut settings: Settings,
+        quit: impl Fn() + Send + 'static,
+    ) -> Result<Self> {
+        let mut terminal = ratatui::try_init()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            let res = run(&mut terminal, &tally, &mut settings, &stopped, &quit);
+            ratatui::restore();
+            res
+        });
+        Ok(Self { stop, thread })
+    }
+
+    /// Gives the terminal back, e.g. to print the session summary.
+    pub fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread
+            .join()
+            .map_err(|_| anyhow!("The leaderboard crashed"))?
+            .map_err(|e| anyhow!("The leaderboard failed: {}", e))
+    }
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    tally: &Mutex<Tally>,
+    settings: &mut Settings,
+    stop: &AtomicBool,
+    quit: &dyn Fn(),
+) -> io::Result<()> {
+    while !stop.load(Ordering::Relaxed) {
+        if let Ok(tally) = tally.lock() {
+            terminal.draw(|frame| draw(frame, &tally, settings))?;
+        }
+        if !event::poll(REFRESH)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => quit(),
+            // The terminal is in raw mode, so Ctrl-C comes in as a key instead of a signal.
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => quit(),
+            KeyCode::Char('s') => settings.sort_by = settings.sort_by.next(),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, tally: &Tally, settings: &Settings) {
+    let [status, leaderboard] =
+        Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+    let sort_by = settings
+        .sort_by
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    frame.render_widget(
+        Paragraph::new(format!(
+            "{} games played, win rate {}%. Sorted by {} (s: change, q: quit)",
+            tally.games_played,
+            win_rate(tally, settings),
+            sort_by
+        )),
+        status,
+    );
+    let widths = [Constraint::Min(12)].into_iter().chain(
+        COLUMNS[1..]
+            .iter()
+            .map(|column| Constraint::Length(column.len().max(6) as u16)),
+    );
+    let table = Table::new(rows(tally, settings).into_iter().map(Row::new), widths)
+        .header(Row::new(COLUMNS).style(Style::new().bold()))
+        .block(Block::bordered().title(" Leaderboard "));
+    frame.render_widget(table, leaderboard);
+}
+
+/// The tracked players as rows of [`COLUMNS`], in the order they are listed.
+pub fn rows(tally: &Tally, settings: &Settings) -> Vec<[String; COLUMNS.len()]> {
+    ranked_players(tally, settings)
+        .into_iter()
+        .map(|(_, stats)| {
+            [
+                stats.shown_name(settings),
+                stats.times_seen.to_string(),
+                format!("{}/{}/{}", stats.wins, stats.losses, stats.draws),
+                stats.mvps.to_string(),
+                stats.score.0.to_string(),
+                stats.per_game(stats.score.0).to_string(),
+                stats
+                    .form
+                    .map_or_else(|| "-".to_string(), |form| format!("{form:.0}")),
+                stats.goals.0.to_string(),
+                stats.assists.0.to_string(),
+                stats.saves.0.to_string(),
+                stats.shots.0.to_string(),
+            ]
+        })
+        .collect()
+}
@@ -0,0 +1,241 @@
+use anyhow::{anyhow, Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// How often to check that the watched folders are still there.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// How long to wait before trying to watch a lost folder again, doubled after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Makes sure `location` is a folder replays can be watched in, creating it first if `create`
+/// is set. A typo in the path would otherwise just mean nothing ever happens.
+pub fn check_location(location: &Path, create: bool) -> Result<()> {
+    if !location.exists() {
+        if !create {
+            return Err(anyhow!(
+                "Replay folder {} does not exist. Check the path given with --location, or pass --create-location to create it",
+                location.to_string_lossy()
+            ));
+        }
+        fs::create_dir_all(location).with_context(|| {
+            format!(
+                "Could not create replay folder {}",
+                location.to_string_lossy()
+            )
+        })?;
+        info!("Created replay folder {}", location.to_string_lossy());
+    }
+    if !location.is_dir() {
+        return Err(anyhow!(
+            "{} is not a folder. Pass the folder replays are saved in with --location",
+            location.to_string_lossy()
+        ));
+    }
+    Ok(())
+}
+
+/// The replay folders being watched. A folder that goes away (deleted and recreated, or on a
+/// network drive that dropped out) stops giving events without the watcher always telling us,
+/// so they are checked every `CHECK_INTERVAL` and watched again once they are back.
+#[derive(Debug)]
+pub struct Locations {
+    mode: RecursiveMode,
+    watching: Vec<PathBuf>,
+    lost: HashMap<PathBuf, Retry>,
+    next_check: Instant,
+}
+
+/// When to try watching a lost folder again.
+#[derive(Debug)]
+struct Retry {
+    at: Instant,
+    backoff: Duration,
+    attempt: usize,
+}
+
+impl Retry {
+    fn new(now: Instant) -> Self {
+        Self {
+            at: now + INITIAL_BACKOFF,
+            backoff: INITIAL_BACKOFF,
+            attempt: 1,
+        }
+    }
+}
+
+impl Locations {
+    /// Starts watching `locations`, with their subfolders if `recursive` is set. Ones that cannot
+    /// be watched right away are tried again later.
+    pub fn watch(
+        watcher: &mut dyn Watcher,
+        locations: &[PathBuf],
+        recursive: bool,
+        now: Instant,
+    ) -> Self {
+        let mut this = Self {
+            mode: if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            },
+            watching: Vec::new(),
+            lost: HashMap::new(),
+            next_check: now + CHECK_INTERVAL,
+        };
+        for location in locations {
+            match watcher.watch(location, this.mode) {
+                Ok(_) => {
+                    info!("Looking for saves in: {}", location.to_string_lossy());
+                    this.watching.push(location.clone());
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not watch {}, trying again later: {}",
+                        location.to_string_lossy(),
+                        e
+                    );
+                    this.lost.insert(location.clone(), Retry::new(now));
+                }
+            }
+        }
+        this
+    }
+
+    /// How many folders are being watched right now.
+    pub fn watching(&self) -> usize {
+        self.watching.len()
+    }
+
+    /// When [`Locations::tick`] has something to do next.
+    pub fn next_deadline(&self) -> Instant {
+        self.lost
+            .values()
+            .map(|retry| retry.at)
+            .fold(self.next_check, Instant::min)
+    }
+
+    /// Stops watching `path` if it is one of the folders, because it was removed or the watcher
+    /// failed on it. Returns whether it was.
+    pub fn lose(&mut self, watcher: &mut dyn Watcher, path: &Path, now: Instant) -> bool {
+        let Some(index) = self.watching.iter().position(|location| location == path) else {
+            return false;
+        };
+        let location = self.watching.remove(index);
+        // The watch is most likely gone already, this only makes sure it is.
+        let _ = watcher.unwatch(&location);
+        warn!(
+            "Lost the watch on {}, watching it again once it is back",
+            location.to_string_lossy()
+        );
+        self.lost.insert(location, Retry::new(now));
+        true
+    }
+
+    /// Loses the folders that are not there anymore.
+    pub fn check(&mut self, watcher: &mut dyn Watcher, now: Instant) {
+        self.next_check = now + CHECK_INTERVAL;
+        let gone: Vec<PathBuf> = self
+            .watching
+            .iter()
+            .filter(|location| !location.is_dir())
+            .cloned()
+            .collect();
+        for location in gone {
+            self.lose(watcher, &location, now);
+        }
+    }
+
+    /// Checks the folders when it is time to, and tries watching the lost ones that are due again.
+    pub fn tick(&mut self, watcher: &mut dyn Watcher, now: Instant) {
+        if now >= self.next_check {
+            self.check(watcher, now);
+        }
+        let due: Vec<PathBuf> = self
+            .lost
+            .iter()
+            .filter(|(_, retry)| retry.at <= now)
+            .map(|(location, _)| location.clone())
+            .collect();
+        for location in due {
+            match watcher.watch(&location, self.mode) {
+                Ok(_) => {
+                    info!("Looking for saves in {} again", location.to_string_lossy());
+                    self.lost.remove(&location);
+                    self.watching.push(location);
+                }
+                Err(e) => {
+                    let Some(retry) = self.lost.get_mut(&location) else {
+                        continue;
+                    };
+                    retry.backoff = (retry.backoff * 2).min(MAX_BACKOFF);
+                    retry.at = now + retry.backoff;
+                    warn!(
+                        "Could not watch {} (attempt {}), trying again in {}s: {}",
+                        location.to_string_lossy(),
+                        retry.attempt,
+                        retry.backoff.as_secs(),
+                        e
+                    );
+                    retry.attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::{Config, RecommendedWatcher};
+
+    #[test]
+    fn check_missing_location() {
+        let dir = std::env::temp_dir().join(format!("rl-session-check-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let error = check_location(&dir, false).unwrap_err().to_string();
+        assert!(error.contains("--create-location"));
+        check_location(&dir, true).unwrap();
+        assert!(dir.is_dir());
+
+        let replay = dir.join("a.replay");
+        fs::write(&replay, b"").unwrap();
+        assert!(check_location(&replay, true).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rewatch_recreated_folder() {
+        let dir = std::env::temp_dir().join(format!("rl-session-watch-{}", std::process::id()));
+        let missing = dir.with_extension("missing");
+        fs::create_dir_all(&dir).unwrap();
+        let mut watcher = RecommendedWatcher::new(|_| {}, Config::default()).unwrap();
+        let start = Instant::now();
+
+        let mut locations = Locations::watch(&mut watcher, &[dir.clone(), missing], false, start);
+        assert_eq!(locations.watching(), 1);
+        assert_eq!(locations.next_deadline(), start + INITIAL_BACKOFF);
+
+        fs::remove_dir(&dir).unwrap();
+        let checked = start + CHECK_INTERVAL;
+        locations.tick(&mut watcher, checked);
+        assert_eq!(locations.watching(), 0);
+        // Still gone on the first retry, so it backs off.
+        locations.tick(&mut watcher, checked + INITIAL_BACKOFF);
+        assert_eq!(locations.watching(), 0);
+        assert_eq!(locations.lost[&dir].at, checked + INITIAL_BACKOFF * 3);
+
+        fs::create_dir(&dir).unwrap();
+        locations.tick(&mut watcher, checked + INITIAL_BACKOFF * 3);
+        assert_eq!(locations.watching(), 1);
+        assert!(locations.lose(&mut watcher, &dir, checked));
+        assert!(!locations.lose(&mut watcher, &dir, checked));
+        fs::remove_dir(&dir).unwrap();
+    }
+}